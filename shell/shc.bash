@@ -0,0 +1,48 @@
+# ShellChat integration for bash, load it in ~/.bashrc with:
+#   eval "$(shc init bash)"
+# Type a request on the prompt line and press Ctrl-G to replace it with the command,
+# `shc fix` corrects the last command recorded by the prompt hook.
+
+_shc_widget() {
+    [[ -z "$READLINE_LINE" ]] && return
+    local output errors exit_code
+    errors="$(mktemp)" || return
+    output="$(shc --print --shell bash -- "$READLINE_LINE" 2>"$errors")"
+    exit_code=$?
+    # Only the command replaces the line, warnings and errors are shown above it
+    [[ -s "$errors" ]] && cat -- "$errors" >&2
+    rm -f -- "$errors"
+    if [[ $exit_code -eq 0 ]]; then
+        READLINE_LINE="$output"
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+}
+
+bind -x '"\C-g": _shc_widget'
+
+# The last command is passed to `shc fix` explicitly, child processes don't see it
+shc() {
+    if [[ "$1" == fix && -n "$_shc_last_command" ]]; then
+        shift
+        command shc fix --last="$_shc_last_command" --exit-code="$_shc_last_exit_code" "$@"
+    else
+        command shc "$@"
+    fi
+}
+
+_shc_precmd() {
+    local exit_code=$?
+    local entry
+    entry="$(HISTTIMEFORMAT= builtin history 1)"
+    if [[ "$entry" =~ ^[[:space:]]*[0-9]+\*?[[:space:]]+(.*)$ ]]; then
+        case "${BASH_REMATCH[1]}" in
+            shc | shc\ *) ;;
+            *) _shc_last_command="${BASH_REMATCH[1]}" _shc_last_exit_code=$exit_code ;;
+        esac
+    fi
+    return $exit_code
+}
+
+if [[ "$PROMPT_COMMAND" != *_shc_precmd* ]]; then
+    PROMPT_COMMAND="_shc_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
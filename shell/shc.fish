@@ -0,0 +1,40 @@
+# ShellChat integration for fish, load it in ~/.config/fish/config.fish with:
+#   shc init fish | source
+# Type a request on the prompt line and press Ctrl-G to replace it with the command,
+# `shc fix` corrects the last command recorded by the postexec hook.
+
+function _shc_widget
+    set -l prompt (commandline)
+    test -z "$prompt"; and return
+    set -l errors (mktemp); or return
+    set -l output (shc --print --shell fish -- "$prompt" 2>$errors)
+    set -l exit_code $status
+    # Only the command replaces the line, warnings and errors are shown above it
+    if test $exit_code -eq 0
+        commandline -r -- (string join \n -- $output)
+    end
+    if test -s $errors
+        echo
+        cat $errors >&2
+    end
+    rm -f $errors
+    commandline -f repaint
+end
+
+bind \cg _shc_widget
+
+# The last command is passed to `shc fix` explicitly, child processes don't see it
+function shc
+    if test "$argv[1]" = fix; and set -q _shc_last_command
+        command shc fix --last=$_shc_last_command --exit-code=$_shc_last_exit_code $argv[2..]
+    else
+        command shc $argv
+    end
+end
+
+function _shc_postexec --on-event fish_postexec
+    set -l exit_code $status
+    string match -qr '^(shc(\s|$)|\s*$)' -- $argv[1]; and return
+    set -g _shc_last_command $argv[1]
+    set -g _shc_last_exit_code $exit_code
+end
@@ -1,7 +1,11 @@
-use clap::Parser;
 use shc_lib::client::{client, ClientCli};
 
 #[tokio::main]
 async fn main() {
-    client(ClientCli::parse()).await
+    std::process::exit(
+        client(
+            ClientCli::try_parse_prompt_from(std::env::args_os()).unwrap_or_else(|err| err.exit()),
+        )
+        .await,
+    )
 }
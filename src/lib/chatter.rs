@@ -1,15 +1,45 @@
-use crate::command::SHELL;
-use crate::common::Question;
-use crate::common::HEADER_API_KEY;
-use crate::spinner::create_spinner;
+use crate::command::{self, edit_in_editor, SHELL};
+use crate::common::{new_request_id, HEADER_API_KEY, HEADER_REQUEST_ID};
+use crate::common::{Answer, CommandOutput, Context, Feedback, Mode, Question, Risk, RiskLevel};
+use crate::history::{History, HistoryEntry};
+use crate::placeholder::{fill, find_placeholders, Placeholder, PlaceholderValues};
+use crate::risk::{merge_risks, RiskAnalyser};
+use crate::sandbox::{Sandbox, SandboxRun};
+use crate::spinner::{create_spinner, run_with_spinner};
 use anyhow::{anyhow, Result};
 use async_recursion::async_recursion;
 use clipboard::ClipboardProvider;
-use inquire::Select;
+use inquire::{Confirm, Password, PasswordDisplayMode, Select, Text};
 use log::debug;
-use reqwest::Client;
-use std::error::Error;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
 use std::process;
+use thiserror::Error;
+
+const PATH_FEEDBACK: &str = "feedback";
+
+#[derive(Debug, Error)]
+pub enum ChatError {
+    #[error("Server not available. Please check the server status and try again.")]
+    Unavailable,
+    #[error("Request to the server failed: {0}")]
+    Request(String),
+    #[error("{status}: {message} (request id: {request_id})")]
+    Status {
+        status: StatusCode,
+        message: String,
+        request_id: String,
+    },
+    #[error("Unexpected answer: {message} (request id: {request_id}). Please use the 'shc' client corresponding to the server.")]
+    UnexpectedAnswer { message: String, request_id: String },
+}
+
+struct RawResponse {
+    status: StatusCode,
+    request_id: Option<String>,
+    text: String,
+}
 
 #[derive(Debug)]
 pub struct Chatter {
@@ -18,6 +48,14 @@ pub struct Chatter {
     os: String,
     shell: String,
     client: Client,
+    candidates: usize,
+    context: Option<Context>,
+    language: Option<String>,
+    history: Option<History>,
+    profile: Option<String>,
+    analyser: RiskAnalyser,
+    sandbox: Option<Sandbox>,
+    capture: bool,
 }
 
 impl Chatter {
@@ -28,100 +66,909 @@ impl Chatter {
             os: os.to_string(),
             shell: shell.to_string(),
             client: Client::new(),
+            candidates: 1,
+            context: None,
+            language: None,
+            history: None,
+            profile: None,
+            analyser: RiskAnalyser::default(),
+            sandbox: None,
+            capture: false,
+        }
+    }
+
+    /// Uses the given HTTP client, e.g. configured with certificates for (mutual) TLS.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Asks for this many alternative commands to choose from.
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// Sends these details of the local environment along with each prompt for a command.
+    pub fn with_context(mut self, context: Option<Context>) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Asks for explanations in this language.
+    pub fn with_language(mut self, language: Option<String>) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Records the executed, copied and cancelled commands in the history.
+    pub fn with_history(mut self, history: Option<History>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// The name of the profile in use, recorded in the history.
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Checks commands with this analyser before they are executed.
+    pub fn with_analyser(mut self, analyser: RiskAnalyser) -> Self {
+        self.analyser = analyser;
+        self
+    }
+
+    /// Offers to try commands in this sandbox before executing them.
+    pub fn with_sandbox(mut self, sandbox: Option<Sandbox>) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Captures the output of executed commands to offer a summary of it afterwards.
+    pub fn with_capture(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    pub async fn chat(&self, prompt: &str, explain: bool) -> Result<Answer, anyhow::Error> {
+        let mode = if explain {
+            Mode::Explain
+        } else {
+            Mode::Command
+        };
+        self.send(&self.build_request(prompt, mode, None)).await
+    }
+
+    /// Asks for a corrected command, the prompt may add details to the failure.
+    pub async fn fix(&self, output: &CommandOutput, prompt: &str) -> Result<Answer, anyhow::Error> {
+        let mut question = self.build_request(prompt, Mode::Fix, None);
+        question.output = Some(output.clone());
+        self.send(&question).await
+    }
+
+    /// Asks for a read-only variant of the command that shows what it would change.
+    pub async fn preview(&self, command: &str) -> Result<Answer, anyhow::Error> {
+        self.send(&self.build_request(command, Mode::Preview, None))
+            .await
+    }
+
+    /// Asks for a summary of the output, or an explanation of the error if the command failed.
+    pub async fn summarize(&self, output: &CommandOutput) -> Result<Answer, anyhow::Error> {
+        let mut question = self.build_request(&output.command, Mode::Summarize, None);
+        question.output = Some(output.clone());
+        self.send(&question).await
+    }
+
+    /// Asks for the steps of the task, to run them one by one.
+    pub async fn plan(&self, prompt: &str) -> Result<Answer, anyhow::Error> {
+        self.send(&self.build_request(prompt, Mode::Plan, None))
+            .await
+    }
+
+    /// Asks for a whole script for the task instead of a command.
+    pub async fn script(&self, prompt: &str) -> Result<Answer, anyhow::Error> {
+        self.send(&self.build_request(prompt, Mode::Script, None))
+            .await
+    }
+
+    /// Asks for a changed command, the server knows the previous prompts and commands of the session.
+    pub async fn refine(&self, session_id: &str, prompt: &str) -> Result<Answer, anyhow::Error> {
+        self.send(&self.build_request(prompt, Mode::Command, Some(session_id)))
+            .await
+    }
+
+    /// Reports a command the user edited, the answer holds the risk of the edited command.
+    pub async fn feedback(
+        &self,
+        session_id: Option<&str>,
+        original: &str,
+        edited: &str,
+    ) -> Result<Answer, anyhow::Error> {
+        let feedback = Feedback {
+            session_id: session_id.map(|id| id.to_string()),
+            original: original.to_string(),
+            edited: edited.to_string(),
+        };
+        self.post(PATH_FEEDBACK, &feedback).await
+    }
+
+    async fn send(&self, question: &Question) -> Result<Answer, anyhow::Error> {
+        self.post("", question).await
+    }
+
+    async fn post<T: Serialize + Sync>(&self, path: &str, body: &T) -> Result<Answer> {
+        let request_id = new_request_id();
+        let response = match unix_socket_path(&self.url) {
+            Some(socket) => self.post_unix(socket, path, &request_id, body).await?,
+            None => self.post_http(path, &request_id, body).await?,
+        };
+
+        let request_id = response.request_id.unwrap_or(request_id);
+        debug!("request id: {}", request_id);
+        if response.status.is_success() {
+            let mut answer: Answer = serde_json::from_str(&response.text).map_err(|err| {
+                ChatError::UnexpectedAnswer {
+                    message: err.to_string(),
+                    request_id: request_id.clone(),
+                }
+            })?;
+            answer.request_id = Some(request_id);
+            Ok(answer)
+        } else {
+            Err(ChatError::Status {
+                status: response.status,
+                message: response.text,
+                request_id,
+            }
+            .into())
         }
     }
 
-    pub async fn chat(&self, prompt: &str, explain: bool) -> Result<String, anyhow::Error> {
+    async fn post_http<T: Serialize + Sync>(
+        &self,
+        path: &str,
+        request_id: &str,
+        body: &T,
+    ) -> Result<RawResponse> {
+        let url = if path.is_empty() {
+            self.url.clone()
+        } else {
+            format!("{}/{}", self.url.trim_end_matches('/'), path)
+        };
         let response = self
             .client
-            .post(&self.url)
+            .post(url)
             .header(HEADER_API_KEY, &self.api_key)
-            .json(&self.build_request(prompt, explain))
+            .header(HEADER_REQUEST_ID, request_id)
+            .json(body)
             .send()
             .await;
 
         match response {
-            Ok(res) => {
-                let status = res.status();
-                let text = res
+            Ok(res) => Ok(RawResponse {
+                status: res.status(),
+                request_id: header_request_id(res.headers()),
+                text: res
                     .text()
                     .await
-                    .unwrap_or_else(|_| "Failed to read the answer".to_string());
-
-                if status.is_success() {
-                    Ok(text)
-                } else {
-                    Err(anyhow!("{}: {}", status, text))
-                }
-            }
+                    .unwrap_or_else(|_| "Failed to read the answer".to_string()),
+            }),
             Err(err) => {
                 if err.is_connect() || err.is_timeout() {
-                    Err(anyhow!(
-                        "Server not available. Please check the server status and try again."
-                    ))
+                    Err(ChatError::Unavailable.into())
                 } else {
-                    Err(anyhow!("Request to the server failed: {}", err))
+                    Err(ChatError::Request(err.to_string()).into())
                 }
             }
         }
     }
 
-    #[async_recursion]
-    pub async fn execute(&self, text: &str) -> Result<(), Box<dyn Error>> {
+    #[cfg(unix)]
+    async fn post_unix<T: Serialize + Sync>(
+        &self,
+        socket: &str,
+        path: &str,
+        request_id: &str,
+        body: &T,
+    ) -> Result<RawResponse> {
+        use http_body_util::{BodyExt, Full};
+        use hyper::body::Bytes;
+        use hyper::header::{CONTENT_TYPE, HOST};
+        use hyper_util::rt::TokioIo;
+
+        let stream = tokio::net::UnixStream::connect(socket)
+            .await
+            .map_err(|_| ChatError::Unavailable)?;
+        let (mut sender, connection) =
+            hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(connection);
+
+        let request = hyper::Request::post(format!("/{}", path))
+            .header(HOST, "localhost")
+            .header(CONTENT_TYPE, "application/json")
+            .header(HEADER_API_KEY, &self.api_key)
+            .header(HEADER_REQUEST_ID, request_id)
+            .body(Full::new(Bytes::from(serde_json::to_vec(body)?)))?;
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(|err| ChatError::Request(err.to_string()))?;
+
+        let status = response.status();
+        let request_id = header_request_id(response.headers());
+        let body = response.into_body().collect().await?.to_bytes();
+        Ok(RawResponse {
+            status,
+            request_id,
+            text: String::from_utf8_lossy(&body).to_string(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    async fn post_unix<T: Serialize + Sync>(
+        &self,
+        _: &str,
+        _: &str,
+        _: &str,
+        _: &T,
+    ) -> Result<RawResponse> {
+        Err(anyhow!(
+            "Unix domain sockets are not supported on this platform"
+        ))
+    }
+
+    pub async fn execute(&self, text: &str) -> Result<()> {
         let spinner = create_spinner("Translating").await;
         let result = self.chat(text, false).await;
         spinner.stop();
+        self.select_action(text, result?).await
+    }
+
+    /// Re-asks once for a command the shell can't parse, e.g. because of broken quoting,
+    /// and warns if the corrected command is still not valid.
+    pub async fn validate(&self, mut answer: Answer) -> Answer {
+        let Some(error) = self.syntax_error(&answer.text) else {
+            return answer;
+        };
+        debug!(
+            "Invalid {} syntax of {:?}: {}",
+            self.shell, answer.text, error
+        );
+        let output = CommandOutput {
+            command: answer.text.clone(),
+            stderr: Some(error.clone()),
+            ..CommandOutput::default()
+        };
+        let note = format!("The command is not valid {} syntax.", self.shell);
+        let error = match run_with_spinner(self.fix(&output, &note), "Checking").await {
+            Ok(fixed) => match self.syntax_error(&fixed.text) {
+                None => {
+                    for candidate in answer.candidates.iter_mut() {
+                        if candidate.text == answer.text {
+                            candidate.text = fixed.text.clone();
+                            candidate.risk = fixed.risk.clone();
+                        }
+                    }
+                    answer.text = fixed.text;
+                    answer.risk = fixed.risk;
+                    return answer;
+                }
+                Some(error) => error,
+            },
+            Err(err) => {
+                debug!("Failed to ask for a valid command: {}", err);
+                error
+            }
+        };
+        eprintln!(
+            "⚠️  The command may not be valid {} syntax: {}",
+            self.shell, error
+        );
+        answer
+    }
 
-        match result {
-            Ok(command) => loop {
-                let answer = Select::new(
-                    command.trim(),
-                    vec!["✅ Execute", "📖 Explain", "📋 Copy", "❌ Cancel"],
-                )
-                    .prompt()?;
+    /// The error of the shell's parse-only mode, placeholders like `<name>` are no redirections.
+    fn syntax_error(&self, text: &str) -> Option<String> {
+        let text = find_placeholders(text)
+            .iter()
+            .fold(text.to_string(), |text, placeholder| {
+                fill(&text, placeholder, "placeholder")
+            });
+        command::syntax_error(&self.shell, &text)
+    }
 
-                match answer {
+    /// Offers the corrected command of the failed one in the usual menu.
+    pub async fn execute_fix(&self, output: &CommandOutput, text: &str) -> Result<()> {
+        let spinner = create_spinner("Fixing").await;
+        let result = self.fix(output, text).await;
+        spinner.stop();
+        let answer = result?;
+        let prompt = if text.trim().is_empty() {
+            format!("Fix `{}`", output.command)
+        } else {
+            format!("Fix `{}`: {}", output.command, text.trim())
+        };
+        self.select_action(&prompt, answer).await
+    }
+
+    /// Offers to execute, skip or edit each step of the plan for the task, a failed step can be fixed.
+    pub async fn execute_plan(&self, text: &str) -> Result<()> {
+        let spinner = create_spinner("Planning").await;
+        let result = self.plan(text).await;
+        spinner.stop();
+        let plan = result?;
+        if plan.steps.is_empty() {
+            return Err(anyhow!("The server answered without any steps."));
+        }
+
+        let count = plan.steps.len();
+        for (i, step) in plan.steps.iter().enumerate() {
+            match &step.explanation {
+                Some(explanation) => println!("{}. {}  # {}", i + 1, step.text, explanation),
+                None => println!("{}. {}", i + 1, step.text),
+            }
+        }
+        let mut values = PlaceholderValues::default();
+        for (i, step) in plan.steps.iter().enumerate() {
+            let prompt = format!("{} (step {} of {})", text, i + 1, count);
+            let mut answer = Answer {
+                text: fill_placeholders(&step.text, &mut values)?,
+                risk: step.risk.clone(),
+                request_id: plan.request_id.clone(),
+                ..Answer::default()
+            };
+            loop {
+                let command = answer.text.clone();
+                let analysis = self.analyser.analyse(&command);
+                let risk = merge_risks(answer.risk.as_ref(), &analysis.risk);
+                match &step.explanation {
+                    Some(explanation) => println!("Step {} of {}: {}", i + 1, count, explanation),
+                    None => println!("Step {} of {}", i + 1, count),
+                }
+                if risk.level > RiskLevel::Low {
+                    print_risk(&risk);
+                    println!("{}", values.mask(&analysis.highlight(&command)).trim());
+                }
+                let mut options = vec!["✅ Execute", "⏭️ Skip", "✏️ Edit", "🛑 Abort"];
+                if risk.level == RiskLevel::Denied {
+                    options.retain(|option| *option != "✅ Execute");
+                }
+                answer.risk = Some(risk);
+
+                let shown = values.mask(&command);
+                match Select::new(shown.trim(), options).prompt()? {
                     "✅ Execute" => {
-                        debug!("{} {:?}", SHELL.cmd, &[&SHELL.arg, &command]);
-                        let code = SHELL.run_command(&command)?;
-                        if code != 0 {
+                        if !confirm_risk(answer.risk.as_ref())? {
+                            println!("The command was not executed.");
+                            continue;
+                        }
+                        debug!("{} {:?}", SHELL.cmd, &[&SHELL.arg, &shown]);
+                        let output = if self.capture {
+                            SHELL.tee_command(&command, &shown)?
+                        } else {
+                            CommandOutput {
+                                command: command.clone(),
+                                exit_code: Some(SHELL.run_command(&command, &shown)?),
+                                ..CommandOutput::default()
+                            }
+                        };
+                        let code = output.exit_code.unwrap_or_default();
+                        self.record(&prompt, &answer, Some(code), &values);
+                        if code == 0 {
+                            break;
+                        }
+                        println!("Step {} failed with exit code {}.", i + 1, code);
+                        if Select::new("What next?", vec!["🔧 Fix", "🛑 Abort"]).prompt()?
+                            == "🛑 Abort"
+                        {
                             process::exit(code);
                         }
+                        let spinner = create_spinner("Fixing").await;
+                        let output = CommandOutput {
+                            command: values.conceal(&output.command),
+                            ..output
+                        };
+                        let result = self.fix(&output, "").await;
+                        spinner.stop();
+                        answer = choose_candidate(result?)?;
+                        answer.text = fill_placeholders(&answer.text, &mut values)?;
                     }
-                    "📖 Explain" => {
-                        let explain_result = self.chat(&command, true).await?;
-                        termimad::print_text(&explain_result);
+                    "⏭️ Skip" => {
+                        self.record(&prompt, &answer, None, &values);
+                        break;
+                    }
+                    "✏️ Edit" => {
+                        let edited = if command.trim().contains('\n') {
+                            edit_in_editor(&command)?
+                        } else {
+                            Text::new("✏️ Edit:")
+                                .with_initial_value(command.trim())
+                                .prompt()?
+                        };
+                        if !edited.trim().is_empty() && edited.trim() != command.trim() {
+                            // The server risk was of the original command, ask for the new one
+                            answer.risk = match self
+                                .feedback(
+                                    answer.session_id.as_deref(),
+                                    &values.conceal(&command),
+                                    &values.conceal(&edited),
+                                )
+                                .await
+                            {
+                                Ok(checked) => checked.risk,
+                                Err(err) => {
+                                    debug!("Failed to report the edited command: {}", err);
+                                    None
+                                }
+                            };
+                            answer.text = edited;
+                        }
+                    }
+                    _ => {
+                        self.record(&prompt, &answer, None, &values);
+                        println!("The plan was aborted at step {}.", i + 1);
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        println!("✅ All {} steps are done.", count);
+        Ok(())
+    }
+
+    /// Offers to execute, explain, edit, refine or copy the command of the answer to the prompt.
+    /// The chosen candidate is validated, the others may never be used.
+    #[async_recursion]
+    pub async fn select_action(&self, prompt: &str, answer: Answer) -> Result<()> {
+        let mut answer = self.validate(choose_candidate(answer)?).await;
+        let mut values = PlaceholderValues::default();
+        answer.text = fill_placeholders(&answer.text, &mut values)?;
+        answer = self.check_executables(answer, &mut values).await?;
+        loop {
+            let command = answer.text.clone();
+            // Secret values are neither shown, recorded nor sent to the server
+            let shown = values.mask(&command);
+            // The server policy and the local analysis of the command both count
+            let analysis = self.analyser.analyse(&command);
+            let risk = merge_risks(answer.risk.as_ref(), &analysis.risk);
+            let mut options = vec![
+                "✅ Execute",
+                "📖 Explain",
+                "🔍 Preview",
+                "🧪 Sandbox",
+                "✏️ Edit",
+                "🔁 Refine",
+                "📋 Copy",
+                "❌ Cancel",
+            ];
+            if risk.level > RiskLevel::Low {
+                print_risk(&risk);
+                println!("{}", values.mask(&analysis.highlight(&command)).trim());
+            }
+            if risk.level == RiskLevel::Denied {
+                options.retain(|option| *option != "✅ Execute");
+            }
+            answer.risk = Some(risk);
+            if answer.session_id.is_none() {
+                options.retain(|option| *option != "🔁 Refine");
+            }
+            if self.sandbox.is_none() {
+                options.retain(|option| *option != "🧪 Sandbox");
+            }
+            let selected = Select::new(shown.trim(), options).prompt()?;
+
+            match selected {
+                "✅ Execute" => {
+                    if !confirm_risk(answer.risk.as_ref())? {
+                        println!("The command was not executed.");
                         continue;
                     }
-                    "📋 Copy" => {
-                        let mut clipboard = clipboard::ClipboardContext::new()?;
-                        clipboard.set_contents(command.to_string())?;
+                    debug!("{} {:?}", SHELL.cmd, &[&SHELL.arg, &shown]);
+                    let output = if self.capture {
+                        Some(SHELL.tee_command(&command, &shown)?)
+                    } else {
+                        None
+                    };
+                    let code = match &output {
+                        Some(output) => output.exit_code.unwrap_or_default(),
+                        None => SHELL.run_command(&command, &shown)?,
+                    };
+                    self.record(prompt, &answer, Some(code), &values);
+                    if let Some(output) = output {
+                        let output = CommandOutput {
+                            command: values.conceal(&output.command),
+                            ..output
+                        };
+                        self.offer_summary(&output).await?;
+                    }
+                    if code != 0 {
+                        process::exit(code);
                     }
-                    "❌ Cancel" => break,
-                    _ => {}
                 }
-                break;
-            },
-            Err(err) => {
-                return Err(err.into());
+                "📖 Explain" => {
+                    let explain_result = self.chat(&values.conceal(&command), true).await?;
+                    termimad::print_text(&explain_result.text);
+                    continue;
+                }
+                "🔍 Preview" => {
+                    let spinner = create_spinner("Previewing").await;
+                    let result = self.preview(&values.conceal(&command)).await;
+                    spinner.stop();
+                    let mut preview = result?;
+                    preview.text = values.fill(&preview.text);
+                    self.run_preview(&preview, &values)?;
+                    continue;
+                }
+                "🧪 Sandbox" => {
+                    if let Some(sandbox) = &self.sandbox {
+                        match SHELL.run_in_sandbox(sandbox, &command) {
+                            Ok(run) => print_sandbox_run(&run),
+                            Err(err) => eprintln!("Error: {}", err),
+                        }
+                    }
+                    continue;
+                }
+                "✏️ Edit" => {
+                    // Multi-line commands are easier to change in a full editor
+                    let edited = if command.trim().contains('\n') {
+                        edit_in_editor(&command)?
+                    } else {
+                        Text::new("✏️ Edit:")
+                            .with_initial_value(command.trim())
+                            .prompt()?
+                    };
+                    if edited.trim().is_empty() || edited.trim() == command.trim() {
+                        continue;
+                    }
+                    answer = match self
+                        .feedback(
+                            answer.session_id.as_deref(),
+                            &values.conceal(&command),
+                            &values.conceal(&edited),
+                        )
+                        .await
+                    {
+                        Ok(checked) => Answer {
+                            text: edited,
+                            ..checked
+                        },
+                        Err(err) => {
+                            debug!("Failed to report the edited command: {}", err);
+                            Answer {
+                                text: edited,
+                                ..Answer::default()
+                            }
+                        }
+                    };
+                    continue;
+                }
+                "🔁 Refine" => {
+                    let follow_up = Text::new("🔁 Refine:")
+                        .with_help_message("e.g. only files modified today")
+                        .prompt()?;
+                    // The session continues with the first candidate, name the chosen one
+                    let follow_up = match answer.candidates.first() {
+                        Some(first) if first.text != command => {
+                            format!(
+                                "Refine the command `{}`: {}",
+                                values.conceal(&command),
+                                follow_up
+                            )
+                        }
+                        _ => follow_up,
+                    };
+                    if let Some(session_id) = &answer.session_id {
+                        let spinner = create_spinner("Refining").await;
+                        let result = self.refine(session_id, &follow_up).await;
+                        spinner.stop();
+                        answer = self.validate(choose_candidate(result?)?).await;
+                        answer.text = fill_placeholders(&answer.text, &mut values)?;
+                        answer = self.check_executables(answer, &mut values).await?;
+                    }
+                    continue;
+                }
+                "📋 Copy" => {
+                    let mut clipboard = clipboard::ClipboardContext::new()
+                        .map_err(|err| anyhow!("Clipboard not available: {}", err))?;
+                    clipboard
+                        .set_contents(command.to_string())
+                        .map_err(|err| anyhow!("Failed to copy the command: {}", err))?;
+                    self.record(prompt, &answer, None, &values);
+                }
+                "❌ Cancel" => self.record(prompt, &answer, None, &values),
+                _ => {}
             }
+            break;
         }
         Ok(())
     }
 
-    fn build_request(&self, prompt: &str, explain: bool) -> Question {
+    /// Warns about the executables of the command that are not installed, and offers to ask
+    /// for a command using only installed tools or to show how to install the missing ones.
+    async fn check_executables(
+        &self,
+        mut answer: Answer,
+        values: &mut PlaceholderValues,
+    ) -> Result<Answer> {
+        // Cmdlets and the many builtins of these shells are no executables
+        if matches!(
+            self.shell.as_str(),
+            "powershell" | "pwsh" | "cmd" | "nushell" | "elvish" | "xonsh"
+        ) {
+            return Ok(answer);
+        }
+        loop {
+            let missing = command::missing_executables(&answer.text);
+            if missing.is_empty() {
+                return Ok(answer);
+            }
+            let names: Vec<String> = missing.iter().map(|name| format!("`{}`", name)).collect();
+            termimad::print_text(&format!("**⚠️  Not installed**: {}", names.join(", ")));
+            let manager = command::package_manager();
+            let mut options = vec!["🔄 Use installed tools"];
+            if manager.is_some() {
+                options.push("📦 Show install command");
+            }
+            options.push("➡️ Continue");
+
+            match Select::new("What next?", options).prompt()? {
+                "🔄 Use installed tools" => {
+                    let output = CommandOutput {
+                        command: values.conceal(&answer.text),
+                        exit_code: Some(127),
+                        stderr: Some(
+                            missing
+                                .iter()
+                                .map(|name| format!("{}: command not found\n", name))
+                                .collect(),
+                        ),
+                        ..CommandOutput::default()
+                    };
+                    let note = format!("Not installed: {}, use other tools.", names.join(", "));
+                    let spinner = create_spinner("Fixing").await;
+                    let result = self.fix(&output, &note).await;
+                    spinner.stop();
+                    answer = self.validate(result?).await;
+                    answer.text = fill_placeholders(&answer.text, values)?;
+                }
+                "📦 Show install command" => {
+                    if let Some(manager) = manager {
+                        println!("📦 {}", command::install_command(manager, &missing));
+                    }
+                    return Ok(answer);
+                }
+                _ => return Ok(answer),
+            }
+        }
+    }
+
+    /// Offers to summarize the captured output, or to explain the error of a failed command.
+    async fn offer_summary(&self, output: &CommandOutput) -> Result<()> {
+        let is_empty =
+            |text: &Option<String>| text.as_deref().is_none_or(|text| text.trim().is_empty());
+        if is_empty(&output.stdout) && is_empty(&output.stderr) {
+            return Ok(());
+        }
+        let option = if output.exit_code == Some(0) {
+            "📝 Summarize output"
+        } else {
+            "🩺 Explain error"
+        };
+        if Select::new("What next?", vec![option, "👋 Done"]).prompt()? == option {
+            let spinner = create_spinner("Summarizing").await;
+            let result = self.summarize(output).await;
+            spinner.stop();
+            termimad::print_text(&result?.text);
+        }
+        Ok(())
+    }
+
+    /// Runs the read-only variant of a command, unless it turns out to be risky itself.
+    fn run_preview(&self, preview: &Answer, values: &PlaceholderValues) -> Result<()> {
+        let command = preview.text.trim();
+        let risk = merge_risks(preview.risk.as_ref(), &self.analyser.analyse(command).risk);
+        println!("🔍 {}", values.mask(command));
+        if risk.level > RiskLevel::Low {
+            print_risk(&risk);
+            println!("The preview is not read-only and was not executed.");
+            return Ok(());
+        }
+        // The policy does not know every command that changes files, e.g. `mv` or `sed -i`
+        if let Some(sandbox) = &self.sandbox {
+            let run = match SHELL.run_in_sandbox(sandbox, command) {
+                Ok(run) => run,
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    return Ok(());
+                }
+            };
+            if !run.changes.is_empty() {
+                print_sandbox_run(&run);
+            } else if run.exit_code != 0 {
+                println!("The preview failed with exit code {}.", run.exit_code);
+            }
+            return Ok(());
+        }
+        if !Confirm::new("Run this preview?")
+            .with_help_message("It should only read, check it like any other command")
+            .with_default(true)
+            .prompt()?
+        {
+            return Ok(());
+        }
+        debug!(
+            "{} {:?}",
+            SHELL.cmd,
+            &[SHELL.arg.clone(), values.mask(command)]
+        );
+        let code = SHELL.run_without_history(command)?;
+        if code != 0 {
+            println!("The preview failed with exit code {}.", code);
+        }
+        Ok(())
+    }
+
+    /// Adds the command with masked secrets to the history, without failing the action if it can't be written.
+    fn record(
+        &self,
+        prompt: &str,
+        answer: &Answer,
+        exit_code: Option<i32>,
+        values: &PlaceholderValues,
+    ) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let mut entry = HistoryEntry::new(prompt, values.mask(&answer.text).trim());
+        entry.executed = exit_code.is_some();
+        entry.exit_code = exit_code;
+        entry.profile = self.profile.clone();
+        entry.risk = answer.risk.clone();
+        entry.request_id = answer.request_id.clone();
+        if let Err(err) = history.append(&entry) {
+            debug!("Failed to record the command in the history: {}", err);
+        }
+    }
+
+    fn build_request(&self, prompt: &str, mode: Mode, session_id: Option<&str>) -> Question {
         Question {
             os: self.os.clone(),
             shell: self.shell.clone(),
             prompt: prompt.to_string(),
-            explain,
+            explain: mode == Mode::Explain,
+            session_id: session_id.map(|id| id.to_string()),
+            candidates: if mode != Mode::Command {
+                1
+            } else {
+                self.candidates
+            },
+            mode,
+            output: None,
+            context: if matches!(mode, Mode::Command | Mode::Plan | Mode::Script) {
+                self.context.clone()
+            } else {
+                None
+            },
+            language: self.language.clone(),
+        }
+    }
+}
+
+/// The server URL `unix:///run/shellchat.sock` (or `unix:/run/shellchat.sock`) addresses a Unix socket.
+fn unix_socket_path(url: &str) -> Option<&str> {
+    url.strip_prefix("unix://")
+        .or_else(|| url.strip_prefix("unix:"))
+}
+
+fn header_request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(HEADER_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// Lets the user pick one of the alternative commands, if the server returned several.
+fn choose_candidate(mut answer: Answer) -> Result<Answer> {
+    if answer.candidates.len() < 2 {
+        return Ok(answer);
+    }
+    let options: Vec<String> = answer
+        .candidates
+        .iter()
+        .map(|candidate| {
+            let marker = match candidate.risk.as_ref().map(|risk| risk.level) {
+                Some(RiskLevel::Denied) => "⛔ ",
+                Some(RiskLevel::High) => "🔥 ",
+                Some(RiskLevel::Medium) => "⚠️  ",
+                _ => "",
+            };
+            match &candidate.explanation {
+                Some(explanation) => {
+                    format!("{}{}  # {}", marker, candidate.text.trim(), explanation)
+                }
+                None => format!("{}{}", marker, candidate.text.trim()),
+            }
+        })
+        .collect();
+    let selected = Select::new("Choose a command", options).raw_prompt()?;
+    let candidate = answer.candidates[selected.index].clone();
+    answer.text = candidate.text;
+    answer.risk = candidate.risk;
+    Ok(answer)
+}
+
+/// Asks for the values of the placeholders the model left in the command, known values are reused.
+fn fill_placeholders(command: &str, values: &mut PlaceholderValues) -> Result<String> {
+    for placeholder in find_placeholders(command) {
+        if values.get(&placeholder).is_some() {
+            continue;
+        }
+        if let Some(value) = ask_placeholder(&placeholder)? {
+            values.insert(placeholder, value);
         }
     }
+    Ok(values.fill(command))
+}
+
+/// The value of an environment variable or the one the user enters, none keeps the placeholder.
+fn ask_placeholder(placeholder: &Placeholder) -> Result<Option<String>> {
+    if let Some((var, value)) = placeholder.env_value() {
+        let message = format!("Use ${} for {}?", var, placeholder.text);
+        if Confirm::new(&message).with_default(true).prompt()? {
+            return Ok(Some(value));
+        }
+    }
+    let message = format!("{}:", placeholder.text);
+    let help = "Enter keeps the placeholder";
+    let value = if placeholder.is_secret() {
+        Password::new(&message)
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .without_confirmation()
+            .with_help_message(help)
+            .prompt()?
+    } else {
+        Text::new(&message).with_help_message(help).prompt()?
+    };
+    Ok(Some(value).filter(|value| !value.trim().is_empty()))
+}
+
+/// High-risk commands are only executed after typing `yes`.
+fn confirm_risk(risk: Option<&Risk>) -> Result<bool> {
+    if risk.is_none_or(|risk| risk.level < RiskLevel::High) {
+        return Ok(true);
+    }
+    let confirmation = Text::new("🔥 Type yes to execute this high-risk command:").prompt()?;
+    Ok(confirmation.trim() == "yes")
+}
+
+fn print_sandbox_run(run: &SandboxRun) {
+    println!(
+        "🧪 The command exited with {} in the sandbox, its changes were discarded.",
+        run.exit_code
+    );
+    if run.changes.is_empty() {
+        println!("No files in the current directory were changed.");
+    }
+    for change in &run.changes {
+        println!("  {}", change);
+    }
+}
+
+pub fn print_risk(risk: &Risk) {
+    let label = match risk.level {
+        RiskLevel::Low => return,
+        RiskLevel::Medium => "⚠️  Medium risk",
+        RiskLevel::High => "🔥 High risk",
+        RiskLevel::Denied => "⛔ Denied by the policy",
+    };
+    termimad::print_text(&format!("**{}**: {}", label, risk.reasons.join(", ")));
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::Candidate;
 
     #[tokio::test]
     async fn test_chat_success() {
@@ -138,4 +985,92 @@ mod tests {
         let result = chatter.execute("echo Hello").await;
         assert!(result.is_err()); // Assuming there's no actual server running during tests
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_unix_socket_path() {
+        assert_eq!(
+            unix_socket_path("unix:///run/shellchat.sock"),
+            Some("/run/shellchat.sock")
+        );
+        assert_eq!(
+            unix_socket_path("unix:/run/shellchat.sock"),
+            Some("/run/shellchat.sock")
+        );
+        assert_eq!(unix_socket_path("http://127.0.0.1:8080"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_chat_over_unix_socket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let path = std::env::temp_dir().join(format!("shc-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let _ = stream.read(&mut buf).await.unwrap();
+            let body = r#"{"text":"ls -la"}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\nx-request-id: 42\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let url = format!("unix://{}", path.display());
+        let chatter = Chatter::new(&url, "test_key", "Linux", "bash");
+        let answer = chatter.chat("list files", false).await.unwrap();
+        assert_eq!(answer.text, "ls -la");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_validate() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let path = std::env::temp_dir().join(format!("shc-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let len = stream.read(&mut buf).await.unwrap();
+            let body = r#"{"text":"echo 'a' > <file name>"}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        });
+
+        let url = format!("unix://{}", path.display());
+        let chatter = Chatter::new(&url, "test_key", "Linux", "sh");
+        let valid = Answer {
+            text: "echo 'a' > <file name>".to_string(),
+            ..Answer::default()
+        };
+        assert_eq!(chatter.validate(valid.clone()).await.text, valid.text);
+
+        let invalid = Answer {
+            text: "echo 'a > <file name>".to_string(),
+            candidates: vec![Candidate {
+                text: "echo 'a > <file name>".to_string(),
+                ..Candidate::default()
+            }],
+            ..Answer::default()
+        };
+        let answer = chatter.validate(invalid).await;
+        assert_eq!(answer.text, valid.text);
+        assert_eq!(answer.candidates[0].text, valid.text);
+        let request = server.await.unwrap();
+        assert!(request.contains(r#""mode":"fix""#));
+        assert!(request.contains("not valid sh syntax"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
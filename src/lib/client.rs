@@ -1,19 +1,32 @@
-use crate::chatter::Chatter;
+use crate::chatter::{print_risk, ChatError, Chatter};
 use crate::command;
-use crate::command::IS_STDOUT_TERMINAL;
-use crate::defaults::DEFAULT_API_KEY;
-use clap::Parser;
+use crate::command::{collect_context, ContextItem, IS_STDOUT_TERMINAL};
+use crate::common::{Answer, CommandOutput, Risk, RiskLevel};
+use crate::config::{config_file, ClientConfig, Profile};
+use crate::defaults::{DEFAULT_API_KEY, DEFAULT_URL};
+use crate::history::{history_file, History};
+use crate::integration::{init_script, IntegrationShell};
+use crate::risk::RiskAnalyser;
+use crate::sandbox::Sandbox;
+use crate::shell_history;
+use crate::spinner::create_spinner;
+use anyhow::{anyhow, Context, Result};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use inquire::Confirm;
+use reqwest::{Certificate, Client, Identity, StatusCode};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, disable_help_subcommand = true)]
 pub struct ClientCli {
-    #[clap(
-        short = 'u',
-        long,
-        env = "SHC_API_URL",
-        default_value = "http://127.0.0.1:8080"
-    )]
-    pub url: String,
+    /// The server URL [default: http://127.0.0.1:8080]
+    #[clap(short = 'u', long, env = "SHC_API_URL")]
+    pub url: Option<String>,
     #[clap(short = 'k', long, env = "SHC_API_KEY")]
     pub key: Option<String>,
     #[clap(short = 'o', long, env = "SHC_ОS")]
@@ -22,11 +35,164 @@ pub struct ClientCli {
     pub shell: Option<String>,
     #[clap(short = 'e', long)]
     pub explain: bool,
+    /// Write only the command to stdout, without any interaction
+    #[clap(short = 'p', long, global = true)]
+    pub print: bool,
+    /// Write the command, its explanation, risk and request ID as JSON to stdout
+    #[clap(long, conflicts_with = "print", global = true)]
+    pub json: bool,
+    /// Number of alternative commands to choose from
+    #[clap(short = 'n', long, env = "SHC_CANDIDATES")]
+    pub candidates: Option<usize>,
+    /// The language of explanations, e.g. `German`
+    #[clap(long, env = "SHC_LANGUAGE")]
+    pub language: Option<String>,
+    /// The profile of the configuration file to use instead of the default one, see `shc config`
+    #[clap(short = 'P', long, env = "SHC_PROFILE")]
+    pub profile: Option<String>,
+    /// PEM CA certificate to trust for the server TLS certificate
+    #[clap(long, env = "SHC_CA_CERT")]
+    pub ca_cert: Option<String>,
+    /// PEM client certificate for mutual TLS
+    #[clap(long, env = "SHC_CLIENT_CERT", requires = "client_key")]
+    pub client_cert: Option<String>,
+    /// PEM (PKCS#8) private key of the client certificate
+    #[clap(long, env = "SHC_CLIENT_KEY", requires = "client_cert")]
+    pub client_key: Option<String>,
+    /// Details of the local environment to send along, e.g. `cwd,git,tools,package-manager,files`
+    #[clap(long, env = "SHC_CONTEXT", value_delimiter = ',')]
+    pub context: Vec<ContextItem>,
+    /// Split the task into steps to execute one by one
+    #[clap(long, conflicts_with = "explain")]
+    pub plan: bool,
+    /// Capture the output of executed commands to offer a summary or an explanation of the error
+    #[clap(long, env = "SHC_CAPTURE")]
+    pub capture: bool,
+    #[command(subcommand)]
+    pub command: Option<ClientCommand>,
     #[clap(trailing_var_arg = true)]
     pub text: Vec<String>,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum ClientCommand {
+    /// Print the shell integration, e.g. `eval "$(shc init bash)"`, to generate commands on the prompt line with Ctrl-G
+    Init { shell: IntegrationShell },
+    /// Correct the last command, taken from the shell integration or the shell history
+    Fix {
+        /// Run the command again to capture its error output, without asking
+        #[clap(long)]
+        rerun: bool,
+        /// What went wrong or what was expected, if the error output does not tell
+        #[clap(short = 'm', long)]
+        note: Option<String>,
+        /// The command to correct instead of the last one in the history, passed by the shell integration
+        #[clap(long, value_name = "COMMAND")]
+        last: Option<String>,
+        /// The exit code of the command given with `--last`
+        #[clap(long, requires = "last", allow_negative_numbers = true)]
+        exit_code: Option<i32>,
+    },
+    /// Write a commented script for the task to a file, after reviewing it
+    Script {
+        /// The file to write the script to, it is made executable
+        #[clap(short = 'o', long)]
+        output: PathBuf,
+        /// Replace the file without asking if it exists
+        #[clap(short = 'f', long)]
+        force: bool,
+        #[clap(trailing_var_arg = true)]
+        text: Vec<String>,
+    },
+    /// Manage the server profiles of the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Search, show, re-run or export previously generated commands
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommand {
+    /// List the latest commands whose prompt or command contain all words of the query
+    Search {
+        /// Number of commands to list
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        #[clap(trailing_var_arg = true)]
+        query: Vec<String>,
+    },
+    /// Show all details of the command with the number listed by `search`
+    Show { number: usize },
+    /// Offer the command with the number listed by `search` again
+    Rerun { number: usize },
+    /// Write the whole history as JSON
+    Export {
+        /// The file to write instead of stdout
+        #[clap(short = 'o', long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// List the profiles, the default one is marked with `*`
+    List,
+    /// Add a profile or replace it, the first profile becomes the default one
+    Add {
+        name: String,
+        #[command(flatten)]
+        profile: Box<Profile>,
+        /// Make it the default profile
+        #[clap(long)]
+        select: bool,
+    },
+    /// Make the profile the default one
+    Select { name: String },
+}
+
 impl ClientCli {
+    /// Parses the arguments like `try_parse_from`, but a prompt starting with the name of a
+    /// subcommand, e.g. `shc history of the last commits`, is taken as text if it is no valid
+    /// subcommand.
+    pub fn try_parse_prompt_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let err = match Self::try_parse_from(&args) {
+            Ok(cli) => return Ok(cli),
+            Err(err) => err,
+        };
+        if matches!(
+            err.kind(),
+            ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+        ) {
+            return Err(err);
+        }
+        let command = Self::command();
+        let is_subcommand = |arg: &OsString| {
+            command
+                .get_subcommands()
+                .any(|subcommand| arg == subcommand.get_name())
+        };
+        let Some(index) = args.iter().skip(1).position(is_subcommand).map(|i| i + 1) else {
+            return Err(err);
+        };
+        // A mistyped option or a missing argument of a subcommand is no prompt
+        let next = args.get(index + 1).map(|arg| arg.to_string_lossy());
+        if next.is_none_or(|next| next.starts_with('-')) {
+            return Err(err);
+        }
+        args.insert(index, "--".into());
+        Self::try_parse_from(&args).map_err(|_| err)
+    }
+
     pub fn text(&self) -> String {
         let text = self
             .text
@@ -36,74 +202,628 @@ impl ClientCli {
             .join(" ");
         text
     }
+
+    pub fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or(DEFAULT_URL)
+    }
+
+    /// Takes the options neither given on the command line nor in the environment from the profile.
+    pub fn apply_profile(&mut self, profile: &Profile) {
+        let profile = profile.clone();
+        self.url = self.url.take().or(profile.url);
+        self.key = self.key.take().or(profile.key);
+        self.os = self.os.take().or(profile.os);
+        self.shell = self.shell.take().or(profile.shell);
+        self.language = self.language.take().or(profile.language);
+        self.candidates = self.candidates.or(profile.candidates);
+        self.ca_cert = self.ca_cert.take().or(profile.ca_cert);
+        if self.client_cert.is_none() && self.client_key.is_none() {
+            self.client_cert = profile.client_cert;
+            self.client_key = profile.client_key;
+        }
+        if self.context.is_empty() {
+            self.context = profile.context.unwrap_or_default();
+        }
+    }
+
+    pub fn shell_name(&self) -> String {
+        self.shell
+            .clone()
+            .unwrap_or_else(|| command::SHELL.name.clone())
+    }
+
+    pub fn http_client(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(ca_cert) = &self.ca_cert {
+            builder = builder.add_root_certificate(Certificate::from_pem(&fs::read(ca_cert)?)?);
+        }
+        if let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) {
+            builder =
+                builder.identity(Identity::from_pkcs8_pem(&fs::read(cert)?, &fs::read(key)?)?);
+        }
+        Ok(builder.build()?)
+    }
 }
 
-pub async fn client(cli: ClientCli) {
-    if !*IS_STDOUT_TERMINAL {
-        eprintln!("I can't recognize an terminal");
-        return;
+/// Exit codes of `shc`, so scripts can tell the failure classes apart.
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_UNAVAILABLE: i32 = 3;
+pub const EXIT_UNAUTHORIZED: i32 = 4;
+pub const EXIT_SERVER_ERROR: i32 = 5;
+pub const EXIT_DENIED: i32 = 6;
+
+/// The `--json` output
+#[derive(Serialize, Debug)]
+struct JsonAnswer {
+    command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    risk: Option<Risk>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+pub async fn client(mut cli: ClientCli) -> i32 {
+    match &cli.command {
+        Some(ClientCommand::Init { shell }) => {
+            print!("{}", init_script(*shell));
+            return EXIT_OK;
+        }
+        Some(ClientCommand::Config { command }) => {
+            return configure(command).unwrap_or_else(report_error);
+        }
+        _ => {}
     }
 
-    let text = cli.text();
-    if text.is_empty() {
-        eprintln!("How can I assist you in your shell?");
-        return;
+    let config = match load_config(&mut cli) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            return EXIT_USAGE;
+        }
+    };
+    let analyser = match risk_analyser(&config, &cli) {
+        Ok(analyser) => analyser,
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            return EXIT_USAGE;
+        }
+    };
+    let history = if config.history.unwrap_or(true) {
+        history_file().map(|path| History::new(&path))
+    } else {
+        None
     };
 
+    if let Some(ClientCommand::History { command }) = &cli.command {
+        if !matches!(command, HistoryCommand::Rerun { .. }) {
+            return show_history(command, history.as_ref()).unwrap_or_else(report_error);
+        }
+    }
+
+    let non_interactive = cli.print || cli.json;
+    if !non_interactive && !*IS_STDOUT_TERMINAL {
+        eprintln!("I can't recognize an terminal, use --print or --json in scripts");
+        return EXIT_USAGE;
+    }
+
     let api_key = cli
         .key
         .clone()
         .unwrap_or_else(|| DEFAULT_API_KEY.to_string());
 
-    let os = cli.os.unwrap_or_else(|| command::OS.clone());
+    let http_client = match cli.http_client() {
+        Ok(http_client) => http_client,
+        Err(err) => {
+            eprintln!("Error: failed to set up TLS: {}", err);
+            return EXIT_USAGE;
+        }
+    };
 
-    let shell = cli.shell.unwrap_or_else(|| command::SHELL.name.clone());
+    let os = cli.os.clone().unwrap_or_else(|| command::OS.clone());
 
-    let chatter = Chatter::new(&cli.url, &api_key, &os, &shell);
+    let chatter = Chatter::new(cli.url(), &api_key, &os, &cli.shell_name())
+        .with_client(http_client)
+        .with_candidates(cli.candidates.unwrap_or(1))
+        .with_context(collect_context(&cli.context))
+        .with_language(cli.language.clone())
+        .with_history(history.clone())
+        .with_profile(cli.profile.clone())
+        .with_analyser(analyser)
+        .with_sandbox(Sandbox::detect())
+        .with_capture(cli.capture);
 
-    if cli.explain {
-        match chatter.chat(&text, true).await {
-            Ok(response) => {
-                termimad::print_text(&response);
-            }
+    match &cli.command {
+        Some(ClientCommand::Fix {
+            rerun,
+            note,
+            last,
+            exit_code,
+        }) => {
+            let last = last.as_ref().map(|command| (command.clone(), *exit_code));
+            return fix(
+                &chatter,
+                &cli,
+                *rerun,
+                note.as_deref().unwrap_or_default(),
+                last,
+            )
+            .await
+            .unwrap_or_else(report_error);
+        }
+        Some(ClientCommand::Script {
+            output,
+            force,
+            text,
+        }) => {
+            return script(&chatter, &cli, output, *force, &text.join(" "))
+                .await
+                .unwrap_or_else(report_error);
+        }
+        Some(ClientCommand::History {
+            command: HistoryCommand::Rerun { number },
+        }) => {
+            return rerun(&chatter, history.as_ref(), *number)
+                .await
+                .unwrap_or_else(report_error);
+        }
+        _ => {}
+    }
+
+    let mut text = cli.text();
+    if text.is_empty() && !std::io::stdin().is_terminal() {
+        text = match std::io::read_to_string(std::io::stdin()) {
+            Ok(input) => input.trim().to_string(),
             Err(err) => {
-                eprintln!("Error: {}", err);
+                eprintln!("Error: failed to read the prompt from stdin: {}", err);
+                return EXIT_USAGE;
+            }
+        };
+    }
+    if text.is_empty() {
+        eprintln!("How can I assist you in your shell?");
+        return EXIT_USAGE;
+    };
+
+    let result = if non_interactive {
+        print_answer(&chatter, &text, &cli).await
+    } else if cli.explain {
+        chatter.chat(&text, true).await.map(|answer| {
+            termimad::print_text(&answer.text);
+            EXIT_OK
+        })
+    } else if cli.plan {
+        chatter.execute_plan(&text).await.map(|_| EXIT_OK)
+    } else {
+        chatter.execute(&text).await.map(|_| EXIT_OK)
+    };
+
+    result.unwrap_or_else(report_error)
+}
+
+/// Applies the selected profile of the configuration file, if there is one.
+fn load_config(cli: &mut ClientCli) -> Result<ClientConfig> {
+    let Some(path) = config_file() else {
+        return Ok(ClientConfig::default());
+    };
+    let config = ClientConfig::load(&path)?;
+    if let Some(profile) = config.profile(cli.profile.as_deref())? {
+        cli.apply_profile(profile);
+        cli.profile = cli.profile.take().or(config.default_profile.clone());
+    }
+    Ok(config)
+}
+
+/// The analyser with the allow and deny lists of the profile in use.
+fn risk_analyser(config: &ClientConfig, cli: &ClientCli) -> Result<RiskAnalyser> {
+    match config.profile(cli.profile.as_deref())? {
+        Some(profile) => RiskAnalyser::new(
+            profile.allow.as_deref().unwrap_or_default(),
+            profile.deny.as_deref().unwrap_or_default(),
+        ),
+        None => Ok(RiskAnalyser::default()),
+    }
+}
+
+fn show_history(command: &HistoryCommand, history: Option<&History>) -> Result<i32> {
+    let history = history.ok_or_else(|| anyhow!("the history is disabled"))?;
+    match command {
+        HistoryCommand::Search { limit, query } => {
+            let query = query.join(" ");
+            let entries = history.load()?;
+            let found: Vec<_> = entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.matches(&query))
+                .collect();
+            for (index, entry) in &found[found.len().saturating_sub(*limit)..] {
+                let status = match (entry.executed, entry.exit_code) {
+                    (true, Some(0)) => "ran".to_string(),
+                    (true, Some(code)) => format!("exit {}", code),
+                    _ => "-".to_string(),
+                };
+                println!(
+                    "{:>5}  {}  {:<7} {}  # {}",
+                    index + 1,
+                    entry
+                        .timestamp
+                        .get(..16)
+                        .unwrap_or(&entry.timestamp)
+                        .replace('T', " "),
+                    status,
+                    entry.command.replace('\n', " "),
+                    entry.prompt.replace('\n', " ")
+                );
+            }
+        }
+        HistoryCommand::Show { number } => {
+            let entry = history
+                .get(*number)?
+                .ok_or_else(|| anyhow!("there is no command {} in the history", number))?;
+            println!("Prompt:     {}", entry.prompt);
+            println!("Command:    {}", entry.command);
+            println!("Time:       {}", entry.timestamp);
+            match (entry.executed, entry.exit_code) {
+                (true, Some(code)) => println!("Executed:   yes, exit code {}", code),
+                (true, None) => println!("Executed:   yes"),
+                _ => println!("Executed:   no"),
+            }
+            if let Some(cwd) = &entry.cwd {
+                println!("Directory:  {}", cwd);
             }
+            if let Some(profile) = &entry.profile {
+                println!("Profile:    {}", profile);
+            }
+            if let Some(risk) = &entry.risk {
+                if risk.reasons.is_empty() {
+                    println!("Risk:       {:?}", risk.level);
+                } else {
+                    println!("Risk:       {:?}, {}", risk.level, risk.reasons.join(", "));
+                }
+            }
+            if let Some(request_id) = &entry.request_id {
+                println!("Request ID: {}", request_id);
+            }
+        }
+        HistoryCommand::Export { output } => {
+            let json = serde_json::to_string_pretty(&history.load()?)?;
+            match output {
+                Some(output) => fs::write(output, json + "\n")?,
+                None => println!("{}", json),
+            }
+        }
+        HistoryCommand::Rerun { .. } => unreachable!("re-runs need the server"),
+    }
+    Ok(EXIT_OK)
+}
+
+/// Offers a command of the history in the usual menu, with the risk it had.
+async fn rerun(chatter: &Chatter, history: Option<&History>, number: usize) -> Result<i32> {
+    let history = history.ok_or_else(|| anyhow!("the history is disabled"))?;
+    let entry = history
+        .get(number)?
+        .ok_or_else(|| anyhow!("there is no command {} in the history", number))?;
+    let answer = Answer {
+        text: entry.command,
+        risk: entry.risk,
+        ..Answer::default()
+    };
+    chatter.select_action(&entry.prompt, answer).await?;
+    Ok(EXIT_OK)
+}
+
+fn configure(command: &ConfigCommand) -> Result<i32> {
+    let path = config_file().ok_or_else(|| anyhow!("the home directory is unknown"))?;
+    let mut config = ClientConfig::load(&path)?;
+    match command {
+        ConfigCommand::List => {
+            if config.profiles.is_empty() {
+                eprintln!(
+                    "No profiles in {}, add one with `shc config add`",
+                    path.display()
+                );
+            }
+            for (name, profile) in &config.profiles {
+                let marker = if config.default_profile.as_deref() == Some(name.as_str()) {
+                    '*'
+                } else {
+                    ' '
+                };
+                println!(
+                    "{} {}\t{}",
+                    marker,
+                    name,
+                    profile.url.as_deref().unwrap_or(DEFAULT_URL)
+                );
+            }
+            return Ok(EXIT_OK);
+        }
+        ConfigCommand::Add {
+            name,
+            profile,
+            select,
+        } => {
+            config.profiles.insert(name.clone(), *profile.clone());
+            if *select || config.default_profile.is_none() {
+                config.select(name)?;
+            }
+        }
+        ConfigCommand::Select { name } => config.select(name)?,
+    }
+    config.save(&path)?;
+    eprintln!("Saved {}", path.display());
+    Ok(EXIT_OK)
+}
+
+fn report_error(err: anyhow::Error) -> i32 {
+    eprintln!("Error: {}", err);
+    exit_code(&err)
+}
+
+/// Asks for a corrected version of the last command, optionally run again to capture its error output.
+/// The command and its exit code are passed by the shell integration, or taken from the history.
+async fn fix(
+    chatter: &Chatter,
+    cli: &ClientCli,
+    rerun: bool,
+    note: &str,
+    last: Option<(String, Option<i32>)>,
+) -> Result<i32> {
+    let shell = cli.shell_name();
+    let (command, exit_code) = match last.filter(|(command, _)| !command.trim().is_empty()) {
+        Some((command, exit_code)) => (Some(command), exit_code),
+        None => (shell_history::last_command(&shell), None),
+    };
+    let Some(command) = command else {
+        eprintln!(
+            "I can't find the last command, load the shell integration with `shc init {}`",
+            shell
+        );
+        return Ok(EXIT_USAGE);
+    };
+
+    let non_interactive = cli.print || cli.json;
+    let rerun = rerun
+        || (!non_interactive
+            && Confirm::new(&format!(
+                "Run `{}` again to capture its error output?",
+                command
+            ))
+            .with_default(false)
+            .prompt()?);
+    let output = if rerun {
+        command::SHELL.capture_command(&command)?
+    } else {
+        CommandOutput {
+            exit_code,
+            command,
+            stdout: None,
+            stderr: None,
         }
+    };
+
+    if non_interactive {
+        let answer = chatter.fix(&output, note).await?;
+        print_command(chatter, answer, cli.json).await
     } else {
-        match chatter.execute(&text).await {
-            Ok(_) => {}
-            Err(err) => eprintln!("Error: {}", err),
+        chatter.execute_fix(&output, note).await.map(|_| EXIT_OK)
+    }
+}
+
+/// Writes the script for the task to the file after the user reviewed it in the pager,
+/// without a terminal only if it passes the syntax check.
+async fn script(
+    chatter: &Chatter,
+    cli: &ClientCli,
+    output: &Path,
+    force: bool,
+    text: &str,
+) -> Result<i32> {
+    if text.trim().is_empty() {
+        eprintln!("Which script should I write?");
+        return Ok(EXIT_USAGE);
+    }
+    let non_interactive = cli.print || cli.json;
+    if non_interactive && !force && output.exists() {
+        eprintln!(
+            "Error: {} exists, use --force to replace it",
+            output.display()
+        );
+        return Ok(EXIT_USAGE);
+    }
+
+    let answer = if non_interactive {
+        chatter.script(text).await?
+    } else {
+        let spinner = create_spinner("Writing").await;
+        let result = chatter.script(text).await;
+        spinner.stop();
+        result?
+    };
+    if let Some(risk) = answer
+        .risk
+        .as_ref()
+        .filter(|risk| risk.level == RiskLevel::Denied)
+    {
+        eprintln!(
+            "Error: the script was denied by the server policy: {}",
+            risk.reasons.join(", ")
+        );
+        return Ok(EXIT_DENIED);
+    }
+    let content = format!("{}\n", answer.text.trim());
+    let shell = cli.shell_name();
+    let syntax_error = command::syntax_error(&shell, &content);
+
+    if non_interactive {
+        if let Some(error) = syntax_error {
+            eprintln!(
+                "Error: the {} syntax check of the script failed:\n{}",
+                shell, error
+            );
+            return Ok(EXIT_ERROR);
+        }
+    } else {
+        command::page(&content)?;
+        if let Some(risk) = answer
+            .risk
+            .as_ref()
+            .filter(|risk| risk.level > RiskLevel::Low)
+        {
+            print_risk(risk);
+        }
+        if let Some(error) = &syntax_error {
+            println!(
+                "⚠️  The {} syntax check of the script failed:\n{}",
+                shell, error
+            );
+        }
+        let question = if output.exists() {
+            format!("Replace {} with the script?", output.display())
+        } else {
+            format!("Write the script to {}?", output.display())
+        };
+        if !Confirm::new(&question)
+            .with_default(syntax_error.is_none())
+            .prompt()?
+        {
+            println!("The script was not written.");
+            return Ok(EXIT_OK);
         }
     }
+
+    write_script(output, &content)?;
+    if cli.json {
+        print_json(&JsonAnswer {
+            command: content,
+            explanation: None,
+            risk: answer.risk,
+            request_id: answer.request_id,
+        })?;
+    } else {
+        println!("{}", output.display());
+    }
+    Ok(EXIT_OK)
+}
+
+/// Writes the script, executable like the files the user creates.
+fn write_script(path: &Path, content: &str) -> Result<()> {
+    fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Writes only the command, or the explanation with `--explain`, to stdout.
+async fn print_answer(chatter: &Chatter, text: &str, cli: &ClientCli) -> Result<i32> {
+    if cli.explain {
+        let answer = chatter.chat(text, true).await?;
+        if cli.json {
+            print_json(&JsonAnswer {
+                command: text.to_string(),
+                explanation: Some(answer.text),
+                risk: None,
+                request_id: answer.request_id,
+            })?;
+        } else {
+            println!("{}", answer.text);
+        }
+        return Ok(EXIT_OK);
+    }
+
+    let answer = if cli.plan {
+        chatter.plan(text).await?
+    } else {
+        chatter.validate(chatter.chat(text, false).await?).await
+    };
+    print_command(chatter, answer, cli.json).await
+}
+
+/// Writes the command, or with `json` the command, its explanation, risk and request ID.
+async fn print_command(chatter: &Chatter, answer: Answer, json: bool) -> Result<i32> {
+    let denied = answer
+        .risk
+        .as_ref()
+        .is_some_and(|risk| risk.level == RiskLevel::Denied);
+    if json {
+        let explanation = chatter.chat(&answer.text, true).await?;
+        print_json(&JsonAnswer {
+            command: answer.text,
+            explanation: Some(explanation.text),
+            risk: answer.risk,
+            request_id: answer.request_id,
+        })?;
+    } else if denied {
+        let reasons = answer.risk.map(|risk| risk.reasons).unwrap_or_default();
+        eprintln!(
+            "Error: the command was denied by the server policy: {}",
+            reasons.join(", ")
+        );
+    } else {
+        println!("{}", answer.text);
+    }
+    Ok(if denied { EXIT_DENIED } else { EXIT_OK })
+}
+
+fn print_json(answer: &JsonAnswer) -> Result<()> {
+    println!("{}", serde_json::to_string(answer)?);
+    Ok(())
+}
+
+fn exit_code(err: &anyhow::Error) -> i32 {
+    match err.downcast_ref::<ChatError>() {
+        Some(ChatError::Unavailable) | Some(ChatError::Request(_)) => EXIT_UNAVAILABLE,
+        Some(ChatError::Status { status, .. }) if *status == StatusCode::UNAUTHORIZED => {
+            EXIT_UNAUTHORIZED
+        }
+        Some(ChatError::Status { .. }) | Some(ChatError::UnexpectedAnswer { .. }) => {
+            EXIT_SERVER_ERROR
+        }
+        None => EXIT_ERROR,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::common::{Question, HEADER_API_KEY};
+    use crate::common::{Mode, Question, HEADER_API_KEY};
+    use crate::history::HistoryEntry;
+    use crate::policy::Policy;
+    use crate::prompts::tests::PROMPTS_CONTENT;
     use crate::prompts::Prompts;
-    use crate::providers::{ProviderApi, ProviderError};
+    use crate::providers::{ChatMessage, ProviderApi, ProviderError};
+    use crate::redaction::Redactor;
     use crate::server::{chat, AppConfig, Config};
+    use crate::session::SessionStore;
     use actix_web::{test, web, App};
     use std::sync::Arc;
 
-    const PROMPTS_CONTENT: &str = r#"
-        explain: "Explain prompt"
-        os_prompt: "Operating system prompt for {os} and {shell}"
-        combinator_powershell: "PowerShell combinator"
-        combinator_default: "Default combinator"
-        additional_instructions: "Additional instructions"
-        "#;
-
     #[test]
     async fn test_client_cli_text() {
         let args = ClientCli {
-            url: "http://localhost:8080".to_string(),
+            url: Some("http://localhost:8080".to_string()),
             key: None,
             os: None,
             shell: None,
             explain: false,
+            print: false,
+            json: false,
+            candidates: None,
+            language: None,
+            profile: None,
+            ca_cert: None,
+            client_cert: None,
+            client_key: None,
+            context: vec![],
+            plan: false,
+            capture: false,
+            command: None,
             text: vec!["Hello, world!".to_string()],
         };
         assert_eq!(args.text(), "Hello, world!");
@@ -112,24 +832,300 @@ mod tests {
     #[tokio::test]
     async fn test_client() {
         let cli = ClientCli {
-            url: "http://localhost:8080".to_string(),
+            url: Some("http://localhost:8080".to_string()),
             key: Some("test_key".to_string()),
             os: None,
             shell: None,
             explain: false,
+            print: false,
+            json: false,
+            candidates: None,
+            language: None,
+            profile: None,
+            ca_cert: None,
+            client_cert: None,
+            client_key: None,
+            context: vec![],
+            plan: false,
+            capture: false,
+            command: None,
             text: vec!["echo Hello".to_string()],
         };
-        client(cli).await;
+        assert_ne!(client(cli).await, EXIT_OK);
+    }
+
+    #[tokio::test]
+    async fn test_client_print_server_not_available() {
+        let cli = ClientCli::try_parse_from([
+            "shc",
+            "--url",
+            "http://127.0.0.1:1",
+            "--print",
+            "list files",
+        ])
+        .unwrap();
+        assert_eq!(client(cli).await, EXIT_UNAVAILABLE);
+    }
+
+    #[test]
+    async fn test_client_cli_parse() {
+        let cli = ClientCli::try_parse_from(["shc", "-p", "list", "files"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.text(), "list files");
+
+        let cli = ClientCli::try_parse_from(["shc", "init", "zsh"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(ClientCommand::Init {
+                shell: IntegrationShell::Zsh
+            })
+        ));
+
+        let cli = ClientCli::try_parse_from(["shc", "fix", "--rerun", "-m", "it should be quiet"])
+            .unwrap();
+        match cli.command {
+            Some(ClientCommand::Fix { rerun, note, .. }) => {
+                assert!(rerun);
+                assert_eq!(note.as_deref(), Some("it should be quiet"));
+            }
+            _ => panic!("expected the fix command"),
+        }
+
+        let cli =
+            ClientCli::try_parse_from(["shc", "fix", "--last=-x ls", "--exit-code", "-1"]).unwrap();
+        match cli.command {
+            Some(ClientCommand::Fix {
+                last, exit_code, ..
+            }) => {
+                assert_eq!(last.as_deref(), Some("-x ls"));
+                assert_eq!(exit_code, Some(-1));
+            }
+            _ => panic!("expected the fix command"),
+        }
+        assert!(ClientCli::try_parse_from(["shc", "fix", "--exit-code", "1"]).is_err());
+
+        let cli =
+            ClientCli::try_parse_from(["shc", "--context", "cwd,package-manager", "ls"]).unwrap();
+        assert_eq!(cli.context, [ContextItem::Cwd, ContextItem::PackageManager]);
+        assert!(!cli.capture);
+
+        let cli = ClientCli::try_parse_from(["shc", "--plan", "set", "up", "a", "venv"]).unwrap();
+        assert!(cli.plan);
+        assert!(ClientCli::try_parse_from(["shc", "--plan", "-e", "ls"]).is_err());
+
+        let cli =
+            ClientCli::try_parse_from(["shc", "--capture", "describe", "the", "pod"]).unwrap();
+        assert!(cli.capture);
+        assert_eq!(cli.text(), "describe the pod");
+
+        let cli = ClientCli::try_parse_from(["shc", "--print", "--", "init the repo"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.text(), "init the repo");
+    }
+
+    #[test]
+    async fn test_client_cli_parse_script() {
+        let cli = ClientCli::try_parse_from([
+            "shc",
+            "script",
+            "-o",
+            "cleanup.sh",
+            "delete",
+            "old",
+            "logs",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(ClientCommand::Script {
+                output,
+                force,
+                text,
+            }) => {
+                assert_eq!(output, PathBuf::from("cleanup.sh"));
+                assert!(!force);
+                assert_eq!(text.join(" "), "delete old logs");
+            }
+            _ => panic!("expected the script command"),
+        }
+        assert!(ClientCli::try_parse_from(["shc", "script", "delete", "old", "logs"]).is_err());
+    }
+
+    #[test]
+    async fn test_client_cli_parse_prompt() {
+        for args in [
+            vec!["shc", "history", "of", "commits"],
+            vec!["shc", "fix", "the", "permissions", "of", "~/.ssh"],
+            vec!["shc", "config", "nginx", "as", "a", "proxy"],
+            vec!["shc", "script", "to", "backup", "home"],
+            vec!["shc", "init", "a", "git", "repo"],
+            vec!["shc", "help", "me", "find", "large", "files"],
+        ] {
+            let cli = ClientCli::try_parse_prompt_from(&args).unwrap();
+            assert!(cli.command.is_none(), "{:?}", args);
+            assert_eq!(cli.text(), args[1..].join(" "));
+        }
+
+        let cli = ClientCli::try_parse_prompt_from(["shc", "--print", "history", "of", "commits"])
+            .unwrap();
+        assert!(cli.print);
+        assert_eq!(cli.text(), "history of commits");
+
+        let cli = ClientCli::try_parse_prompt_from(["shc", "history", "show", "42"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(ClientCommand::History {
+                command: HistoryCommand::Show { number: 42 }
+            })
+        ));
+        let cli = ClientCli::try_parse_prompt_from(["shc", "fix"]).unwrap();
+        assert!(matches!(cli.command, Some(ClientCommand::Fix { .. })));
+        assert!(ClientCli::try_parse_prompt_from(["shc", "--unknown", "ls"]).is_err());
+        assert!(ClientCli::try_parse_prompt_from(["shc", "history", "--bogus"]).is_err());
+        assert!(ClientCli::try_parse_prompt_from(["shc", "history"]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    async fn test_write_script() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("shc-{}.sh", uuid::Uuid::new_v4()));
+        write_script(&path, "#!/bin/sh\necho hi\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#!/bin/sh\necho hi\n");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    async fn test_client_cli_parse_config() {
+        let cli = ClientCli::try_parse_from([
+            "shc",
+            "config",
+            "add",
+            "team",
+            "--url",
+            "https://team",
+            "--context",
+            "cwd,git",
+            "--select",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(ClientCommand::Config {
+                command:
+                    ConfigCommand::Add {
+                        name,
+                        profile,
+                        select,
+                    },
+            }) => {
+                assert_eq!(name, "team");
+                assert_eq!(profile.url.as_deref(), Some("https://team"));
+                assert_eq!(
+                    profile.context,
+                    Some(vec![ContextItem::Cwd, ContextItem::Git])
+                );
+                assert!(select);
+            }
+            _ => panic!("expected the config add command"),
+        }
+        assert!(ClientCli::try_parse_from(["shc", "config", "select"]).is_err());
+    }
+
+    #[test]
+    async fn test_show_history() {
+        let dir = std::env::temp_dir().join(format!("shc-{}", uuid::Uuid::new_v4()));
+        let history = History::new(&dir.join("history.jsonl"));
+        history
+            .append(&HistoryEntry::new("list files", "ls"))
+            .unwrap();
+
+        let output = dir.join("export.json");
+        let export = HistoryCommand::Export {
+            output: Some(output.display().to_string()),
+        };
+        assert_eq!(show_history(&export, Some(&history)).unwrap(), EXIT_OK);
+        let exported: Vec<HistoryEntry> =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(exported, history.load().unwrap());
+
+        assert!(show_history(&HistoryCommand::Show { number: 2 }, Some(&history)).is_err());
+        assert!(show_history(&export, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let cli = ClientCli::try_parse_from(["shc", "history", "search", "-n", "5", "git", "log"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(ClientCommand::History {
+                command: HistoryCommand::Search { limit: 5, query }
+            }) if query == ["git", "log"]
+        ));
+    }
+
+    #[test]
+    async fn test_apply_profile() {
+        let mut cli = ClientCli::try_parse_from(["shc", "-n", "2", "list", "files"]).unwrap();
+        assert_eq!(cli.url(), DEFAULT_URL);
+        cli.apply_profile(&Profile {
+            url: Some("https://team".to_string()),
+            key: Some("secret".to_string()),
+            candidates: Some(3),
+            language: Some("German".to_string()),
+            context: Some(vec![ContextItem::Cwd]),
+            client_cert: Some("client.pem".to_string()),
+            ..Profile::default()
+        });
+        assert_eq!(cli.url(), "https://team");
+        assert_eq!(cli.key.as_deref(), Some("secret"));
+        assert_eq!(cli.candidates, Some(2));
+        assert_eq!(cli.language.as_deref(), Some("German"));
+        assert_eq!(cli.context, [ContextItem::Cwd]);
+        assert_eq!(cli.client_cert.as_deref(), Some("client.pem"));
+    }
+
+    #[test]
+    async fn test_exit_code() {
+        let status = |status| ChatError::Status {
+            status,
+            message: String::new(),
+            request_id: String::new(),
+        };
+        assert_eq!(
+            exit_code(&status(StatusCode::UNAUTHORIZED).into()),
+            EXIT_UNAUTHORIZED
+        );
+        assert_eq!(
+            exit_code(&status(StatusCode::INTERNAL_SERVER_ERROR).into()),
+            EXIT_SERVER_ERROR
+        );
+        assert_eq!(exit_code(&ChatError::Unavailable.into()), EXIT_UNAVAILABLE);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), EXIT_ERROR);
     }
 
     #[test]
     async fn test_client_cli_text_empty() {
         let args = ClientCli {
-            url: "http://localhost:8080".to_string(),
+            url: Some("http://localhost:8080".to_string()),
             key: None,
             os: None,
             shell: None,
             explain: false,
+            print: false,
+            json: false,
+            candidates: None,
+            language: None,
+            profile: None,
+            ca_cert: None,
+            client_cert: None,
+            client_key: None,
+            context: vec![],
+            plan: false,
+            capture: false,
+            command: None,
             text: vec![],
         };
         assert_eq!(args.text(), "");
@@ -139,11 +1135,7 @@ mod tests {
 
     #[async_trait::async_trait]
     impl ProviderApi for MockProvider {
-        async fn call(
-            &self,
-            _role_prompt: &str,
-            _user_prompt: &str,
-        ) -> Result<String, ProviderError> {
+        async fn chat(&self, _messages: &[ChatMessage]) -> Result<String, ProviderError> {
             Ok("Mock response".to_string())
         }
     }
@@ -153,6 +1145,9 @@ mod tests {
         let app_config = Arc::new(AppConfig {
             provider: Arc::new(MockProvider {}),
             prompts: Prompts::from_yaml_content(PROMPTS_CONTENT),
+            redactor: Redactor::default(),
+            policy: Policy::default(),
+            sessions: SessionStore::default(),
         });
 
         let app = test::init_service(
@@ -184,6 +1179,9 @@ mod tests {
         let app_config = Arc::new(AppConfig {
             provider: Arc::new(MockErrorProvider {}),
             prompts: Prompts::from_yaml_content(PROMPTS_CONTENT),
+            redactor: Redactor::default(),
+            policy: Policy::default(),
+            sessions: SessionStore::default(),
         });
 
         let app = test::init_service(
@@ -199,6 +1197,12 @@ mod tests {
             shell: "bash".to_string(),
             prompt: "What is Rust?".to_string(),
             explain: false,
+            session_id: None,
+            candidates: 1,
+            mode: Mode::Command,
+            output: None,
+            context: None,
+            language: None,
         };
         let req = test::TestRequest::post()
             .uri("/")
@@ -214,11 +1218,7 @@ mod tests {
 
     #[async_trait::async_trait]
     impl ProviderApi for MockErrorProvider {
-        async fn call(
-            &self,
-            _role_prompt: &str,
-            _user_prompt: &str,
-        ) -> Result<String, ProviderError> {
+        async fn chat(&self, _messages: &[ChatMessage]) -> Result<String, ProviderError> {
             Err(ProviderError::UnexpectedResponse("error".to_string()))
         }
     }
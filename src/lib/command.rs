@@ -1,6 +1,16 @@
-use anyhow::Result;
-use std::io::IsTerminal;
-use std::{env, process::Command};
+use crate::common::{tail, CommandOutput, Context, MAX_CONTEXT_ENTRIES, MAX_OUTPUT_LEN};
+use crate::risk::split_commands;
+use crate::sandbox::{Sandbox, SandboxRun};
+use crate::shell_history::{append_history, history_file};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
 
 lazy_static::lazy_static! {
     pub static ref OS: String = detect_os();
@@ -26,36 +36,492 @@ pub struct Shell {
     pub name: String,
     pub cmd: String,
     pub arg: String,
-    pub history_cmd: Option<String>,
+    /// Executed commands are appended to it, if the format of the shell is known
+    pub history_file: Option<PathBuf>,
 }
 
 impl Shell {
-    pub fn new(name: &str, cmd: &str, arg: &str, history_cmd: Option<&str>) -> Self {
+    pub fn new(name: &str, cmd: &str, arg: &str) -> Self {
         Self {
             name: name.to_string(),
             cmd: cmd.to_string(),
             arg: arg.to_string(),
-            history_cmd: history_cmd.map(|cmd| cmd.to_string()),
+            history_file: history_file(name),
         }
     }
 
-    pub fn run_command(&self, eval_str: &str) -> Result<i32> {
+    /// Runs the command and adds the entry to the history if it succeeded,
+    /// e.g. the command with its secrets masked.
+    pub fn run_command(&self, eval_str: &str, entry: &str) -> Result<i32> {
+        let code = self.run_without_history(eval_str)?;
+
+        if code == 0 {
+            self.add_to_history(entry);
+        }
+
+        Ok(code)
+    }
+
+    /// Runs the command like `run_command`, copying its output to the terminal
+    /// and keeping the tail of both streams, e.g. to summarize it afterwards.
+    pub fn tee_command(&self, eval_str: &str, entry: &str) -> Result<CommandOutput> {
+        let mut child = Command::new(&self.cmd)
+            .arg(&self.arg)
+            .arg(eval_str)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .map(|stdout| thread::spawn(move || tee(stdout, io::stdout())));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|stderr| thread::spawn(move || tee(stderr, io::stderr())));
+        let status = child.wait()?;
+
+        let collect = |handle: Option<thread::JoinHandle<Vec<u8>>>| {
+            let bytes = handle?.join().ok()?;
+            Some(tail(&String::from_utf8_lossy(&bytes), MAX_OUTPUT_LEN).to_string())
+        };
+        let output = CommandOutput {
+            command: eval_str.to_string(),
+            exit_code: status.code(),
+            stdout: collect(stdout),
+            stderr: collect(stderr),
+        };
+        if status.success() {
+            self.add_to_history(entry);
+        }
+        Ok(output)
+    }
+
+    /// Appends the command to the history file of the shell, as if it was typed.
+    fn add_to_history(&self, entry: &str) {
+        let Some(path) = &self.history_file else {
+            return;
+        };
+        if let Err(err) = append_history(path, &self.name, entry) {
+            debug!("Failed to add the command to {}: {}", path.display(), err);
+        }
+    }
+
+    /// Runs the command in the sandbox instead, its changes of the working directory are discarded.
+    pub fn run_in_sandbox(&self, sandbox: &Sandbox, eval_str: &str) -> Result<SandboxRun> {
+        sandbox.run(&self.cmd, &self.arg, eval_str)
+    }
+
+    /// Runs the command without adding it to the history, e.g. the preview of another command.
+    pub fn run_without_history(&self, eval_str: &str) -> Result<i32> {
         let status = Command::new(&self.cmd)
             .arg(&self.arg)
             .arg(eval_str)
             .status()?;
+        Ok(status.code().unwrap_or_default())
+    }
 
-        if status.success() {
-            if let Some(history_cmd) = &self.history_cmd {
-                let _ = Command::new(&self.cmd)
-                    .arg(&self.arg)
-                    .arg(format!("{} \"{}\"", history_cmd, eval_str))
-                    .status();
+    /// Runs the command again to capture its error output, which is still shown to the user.
+    pub fn capture_command(&self, eval_str: &str) -> Result<CommandOutput> {
+        let output = Command::new(&self.cmd)
+            .arg(&self.arg)
+            .arg(eval_str)
+            .stderr(Stdio::piped())
+            .output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        eprint!("{}", stderr);
+
+        Ok(CommandOutput {
+            command: eval_str.to_string(),
+            exit_code: output.status.code(),
+            stdout: None,
+            stderr: Some(tail(&stderr, MAX_OUTPUT_LEN).to_string()),
+        })
+    }
+}
+
+/// The error of the shell's parse-only mode for the code, `None` if it is valid
+/// or the shell can't check it, e.g. if it is not installed.
+pub fn syntax_error(shell: &str, code: &str) -> Option<String> {
+    let (cmd, arg) = match shell {
+        "bash" | "zsh" | "sh" | "dash" | "ksh" => (shell, "-n"),
+        "fish" => ("fish", "--no-execute"),
+        _ => return None,
+    };
+    let mut child = Command::new(cmd)
+        .arg(arg)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(code.as_bytes());
+    }
+    let output = child.wait_with_output().ok()?;
+    if output.status.success() {
+        return None;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Some(if stderr.is_empty() {
+        format!("{} {} failed", cmd, arg)
+    } else {
+        stderr
+    })
+}
+
+/// Shows the text in `$PAGER`, by default `less`, or prints it if there is no pager.
+pub fn page(text: &str) -> Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less -FX".to_string());
+    let mut parts = pager.split_whitespace();
+    let child = parts.next().and_then(|program| {
+        Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .spawn()
+            .ok()
+    });
+    match child {
+        Some(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(text.as_bytes());
             }
+            child.wait()?;
         }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
 
-        Ok(status.code().unwrap_or_default())
+/// Copies the stream to the writer as it comes and returns its last bytes,
+/// enough for `MAX_OUTPUT_LEN` characters.
+fn tee(mut reader: impl Read, mut writer: impl Write) -> Vec<u8> {
+    let limit = MAX_OUTPUT_LEN * 4;
+    let mut kept = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => {
+                let _ = writer.write_all(&buffer[..read]);
+                let _ = writer.flush();
+                kept.extend_from_slice(&buffer[..read]);
+                if kept.len() > 2 * limit {
+                    kept.drain(..kept.len() - limit);
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    if kept.len() > limit {
+        kept.drain(..kept.len() - limit);
+    }
+    kept
+}
+
+/// Opens the text in `$VISUAL` or `$EDITOR` and returns the saved text.
+pub fn edit_in_editor(text: &str) -> Result<String> {
+    let default_editor = if env::consts::OS == "windows" {
+        "notepad"
+    } else {
+        "vi"
+    };
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| default_editor.to_string());
+    edit_with(&editor, text)
+}
+
+fn edit_with(editor: &str, text: &str) -> Result<String> {
+    let path = env::temp_dir().join(format!("shc-{}.sh", uuid::Uuid::new_v4()));
+    std::fs::write(&path, text)?;
+
+    // The editor may come with arguments, e.g. `code --wait`
+    let mut args = editor.split_whitespace();
+    let program = args.next().ok_or_else(|| anyhow!("No editor configured"))?;
+    let status = Command::new(program).args(args).arg(&path).status();
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    if !status?.success() {
+        return Err(anyhow!("The editor {} failed", editor));
+    }
+    Ok(edited?.trim_end().to_string())
+}
+
+/// Tools worth knowing about to pick the right command, e.g. `rg` instead of `grep`
+const CONTEXT_TOOLS: &[&str] = &[
+    "git",
+    "docker",
+    "podman",
+    "kubectl",
+    "helm",
+    "terraform",
+    "aws",
+    "gcloud",
+    "az",
+    "jq",
+    "yq",
+    "rg",
+    "fd",
+    "fzf",
+    "curl",
+    "wget",
+    "python3",
+    "node",
+    "cargo",
+    "go",
+];
+
+/// System package managers in order of preference, the first one found is reported
+const PACKAGE_MANAGERS: &[&str] = &[
+    "apt", "dnf", "yum", "pacman", "zypper", "apk", "nix-env", "brew", "port", "winget", "choco",
+    "scoop",
+];
+
+/// A detail of the local environment that is only sent to the server when enabled.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContextItem {
+    /// The current working directory
+    Cwd,
+    /// Whether the directory is in a git repository, and its branch
+    Git,
+    /// Which well-known tools are installed
+    Tools,
+    /// The system package manager
+    PackageManager,
+    /// A truncated listing of the current directory
+    Files,
+}
+
+/// Collects the enabled details, nothing at all if none is enabled.
+pub fn collect_context(items: &[ContextItem]) -> Option<Context> {
+    if items.is_empty() {
+        return None;
+    }
+    let cwd = env::current_dir().ok();
+    let mut context = Context::default();
+    for item in items {
+        match item {
+            ContextItem::Cwd => context.cwd = cwd.as_ref().map(|cwd| cwd.display().to_string()),
+            ContextItem::Git => context.git_branch = git_branch(),
+            ContextItem::Tools => {
+                context.tools = CONTEXT_TOOLS
+                    .iter()
+                    .filter(|tool| find_executable(tool).is_some())
+                    .map(|tool| tool.to_string())
+                    .collect()
+            }
+            ContextItem::PackageManager => {
+                context.package_manager = package_manager().map(|manager| manager.to_string())
+            }
+            ContextItem::Files => {
+                if let Some(cwd) = &cwd {
+                    (context.files, context.more_files) = list_dir(cwd, MAX_CONTEXT_ENTRIES);
+                }
+            }
+        }
+    }
+    Some(context)
+}
+
+fn git_branch() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Some(branch).filter(|branch| !branch.is_empty())
+}
+
+/// The sorted names of the visible entries and how many more were left out.
+fn list_dir(dir: &Path, max_entries: usize) -> (Vec<String>, usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (Vec::new(), 0);
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                return None;
+            }
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            Some(if is_dir { format!("{}/", name) } else { name })
+        })
+        .collect();
+    names.sort();
+    let more = names.len().saturating_sub(max_entries);
+    names.truncate(max_entries);
+    (names, more)
+}
+
+/// The path of the executable in the `PATH`, also trying the `.exe` extension on Windows.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if env::consts::OS == "windows" {
+            let candidate = dir.join(format!("{}.exe", name));
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+        None
+    })
+}
+
+/// The first of the system package managers which is installed.
+pub fn package_manager() -> Option<&'static str> {
+    PACKAGE_MANAGERS
+        .iter()
+        .find(|manager| find_executable(manager).is_some())
+        .copied()
+}
+
+/// The command to install the packages providing the executables with the package manager.
+pub fn install_command(manager: &str, executables: &[String]) -> String {
+    let packages: Vec<String> = executables
+        .iter()
+        .map(|executable| {
+            let package = match (manager, executable.as_str()) {
+                ("apt", "fd") => "fd-find",
+                (_, "rg") => "ripgrep",
+                ("apt" | "dnf" | "yum" | "zypper" | "apk" | "pacman", "node") => "nodejs",
+                _ => executable,
+            };
+            match manager {
+                "nix-env" => format!("nixpkgs.{}", package),
+                _ => package.to_string(),
+            }
+        })
+        .collect();
+    let install = match manager {
+        "apt" | "dnf" | "yum" | "zypper" => format!("sudo {} install", manager),
+        "pacman" => "sudo pacman -S".to_string(),
+        "apk" => "sudo apk add".to_string(),
+        "nix-env" => "nix-env -iA".to_string(),
+        "port" => "sudo port install".to_string(),
+        _ => format!("{} install", manager),
+    };
+    format!("{} {}", install, packages.join(" "))
+}
+
+/// Words that start a command without being an executable, the command follows them.
+const COMMAND_PREFIXES: &[&str] = &[
+    "sudo", "doas", "env", "time", "nohup", "nice", "timeout", "exec", "command", "noglob",
+    "xargs", "if", "then", "else", "elif", "while", "until", "do", "!", "{", "(", "and", "or",
+    "not", "begin",
+];
+
+/// The options of the prefixes whose value is the next word, e.g. `sudo -u postgres psql`.
+const PREFIX_OPTIONS_WITH_VALUE: &[(&str, &str)] = &[
+    (
+        "sudo",
+        "-u -g -C -D -h -p -r -t -U -T --user --group --chdir --host --prompt",
+    ),
+    ("doas", "-u -C"),
+    ("env", "-u -C -S --unset --chdir --split-string"),
+    ("nice", "-n --adjustment"),
+    ("time", "-f -o --format --output"),
+    ("timeout", "-k -s --kill-after --signal"),
+    ("exec", "-a"),
+    (
+        "xargs",
+        "-a -d -E -I -L -n -P -s --arg-file --delimiter --max-args",
+    ),
+];
+
+/// Words that start a part of a command without any executable, e.g. `for f in *.txt`.
+const NO_COMMAND: &[&str] = &[
+    "for", "case", "select", "function", "done", "fi", "esac", "}", ")", "in", "[[", "((",
+    "switch", "end",
+];
+
+/// Shell builtins that are no executables in the `PATH`, including those of zsh and fish.
+const BUILTINS: &[&str] = &[
+    ".", ":", "alias", "bg", "bind", "break", "builtin", "cd", "continue", "declare", "dirs",
+    "disown", "echo", "eval", "exit", "export", "fg", "hash", "history", "jobs", "let", "local",
+    "popd", "printf", "pushd", "pwd", "read", "readonly", "return", "set", "shift", "shopt",
+    "source", "test", "[", "trap", "type", "typeset", "ulimit", "umask", "unalias", "unset",
+    "wait", "true", "false", "kill", "setenv", "unsetenv", "autoload", "bindkey", "emulate",
+    "print", "rehash", "setopt", "unsetopt", "whence", "where", "zle", "zmodload", "abbr",
+    "argparse", "contains", "count", "math", "path", "random", "status", "string",
+];
+
+/// The distinct executables the command runs, without builtins, variables and paths.
+pub fn executables(command: &str) -> Vec<String> {
+    let mut executables: Vec<String> = Vec::new();
+    for range in split_commands(command) {
+        let mut prefix: Option<&str> = None;
+        let mut skip_value = false;
+        for word in command[range].split_whitespace() {
+            if std::mem::take(&mut skip_value) {
+                continue;
+            }
+            // The executable in a variable is unknown
+            if word.starts_with('$') && !word.starts_with("$(") {
+                break;
+            }
+            let word = word
+                .trim_start_matches(['$', '(', '`'])
+                .trim_end_matches([')', '`']);
+            if NO_COMMAND.contains(&word) {
+                break;
+            }
+            if COMMAND_PREFIXES.contains(&word) {
+                prefix = Some(word);
+                continue;
+            }
+            // Options of prefixes with their values, e.g. the durations of `timeout`
+            if let Some(prefix) = prefix {
+                if word.starts_with('-') {
+                    skip_value = PREFIX_OPTIONS_WITH_VALUE.iter().any(|(name, options)| {
+                        *name == prefix && options.split(' ').any(|option| option == word)
+                    });
+                    continue;
+                }
+                if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    continue;
+                }
+            }
+            // Variable assignments and subshells before the command
+            if word.is_empty() || is_assignment(word) {
+                continue;
+            }
+            let is_name = word
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '+'));
+            if is_name && !BUILTINS.contains(&word) && !executables.iter().any(|e| e == word) {
+                executables.push(word.to_string());
+            }
+            break;
+        }
     }
+    executables
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+/// The executables of the command that are not in the `PATH`.
+pub fn missing_executables(command: &str) -> Vec<String> {
+    executables(command)
+        .into_iter()
+        .filter(|executable| find_executable(executable).is_none())
+        .collect()
 }
 
 pub fn detect_shell() -> Shell {
@@ -65,9 +531,9 @@ pub fn detect_shell() -> Shell {
             let v = v.to_lowercase();
             if v.split(';').count() >= 3 {
                 if v.contains("powershell\\7\\") {
-                    Some(Shell::new("pwsh", "pwsh.exe", "-c", None))
+                    Some(Shell::new("pwsh", "pwsh.exe", "-c"))
                 } else {
-                    Some(Shell::new("powershell", "powershell.exe", "-Command", None))
+                    Some(Shell::new("powershell", "powershell.exe", "-Command"))
                 }
             } else {
                 None
@@ -75,26 +541,83 @@ pub fn detect_shell() -> Shell {
         }) {
             ret
         } else {
-            Shell::new("cmd", "cmd.exe", "/C", None)
+            Shell::new("cmd", "cmd.exe", "/C")
         }
     } else {
-        let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-        let shell = match shell.rsplit_once('/') {
-            Some((_, v)) => v,
-            None => &shell,
-        };
-        match shell {
-            "bash" => Shell::new(shell, shell, "-c", Some("history -s")),
-            "zsh" => Shell::new(shell, shell, "-c", Some("print -s")),
-            "fish" | "pwsh" => Shell::new(shell, shell, "-c", None),
-            _ => Shell::new("sh", "sh", "-c", None),
+        // The login shell in `$SHELL` may not be the one the user is typing in
+        parent_process()
+            .and_then(|name| known_shell(&name))
+            .or_else(|| env::var("SHELL").ok().and_then(|shell| known_shell(&shell)))
+            .unwrap_or_else(|| Shell::new("sh", "sh", "-c"))
+    }
+}
+
+/// The shell for a process name or path, e.g. `-bash` of a login shell or `/usr/bin/nu`.
+fn known_shell(name: &str) -> Option<Shell> {
+    let name = name.trim().trim_start_matches('-');
+    let name = name.rsplit('/').next().unwrap_or(name);
+    Some(match name {
+        "bash" | "zsh" | "fish" | "pwsh" | "xonsh" | "elvish" | "tcsh" | "csh" => {
+            Shell::new(name, name, "-c")
         }
+        "nu" | "nushell" => Shell::new("nushell", "nu", "-c"),
+        "sh" | "dash" | "ash" | "ksh" => Shell::new("sh", "sh", "-c"),
+        _ => return None,
+    })
+}
+
+/// The name of the parent process, xonsh may run as `python3 -m xonsh`.
+#[cfg(target_os = "linux")]
+fn parent_process() -> Option<String> {
+    let proc_dir = PathBuf::from(format!("/proc/{}", std::os::unix::process::parent_id()));
+    let comm = std::fs::read_to_string(proc_dir.join("comm")).ok()?;
+    let comm = comm.trim();
+    if comm.starts_with("python") {
+        let cmdline = std::fs::read(proc_dir.join("cmdline")).ok()?;
+        return cmdline
+            .split(|&byte| byte == 0)
+            .any(|arg| arg.ends_with(b"xonsh"))
+            .then(|| "xonsh".to_string());
     }
+    Some(comm.to_string())
+}
+
+/// The name of the parent process from `ps`, e.g. on macOS and the BSDs.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn parent_process() -> Option<String> {
+    let ps = |format: &str| {
+        let ppid = std::os::unix::process::parent_id().to_string();
+        let output = Command::new("ps")
+            .args(["-o", format, "-p", &ppid])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let comm = ps("comm=")?;
+    if Path::new(&comm)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("python"))
+    {
+        return ps("args=")?
+            .split_whitespace()
+            .any(|arg| arg.ends_with("xonsh"))
+            .then(|| "xonsh".to_string());
+    }
+    Some(comm)
+}
+
+#[cfg(not(unix))]
+fn parent_process() -> Option<String> {
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::shell_history;
 
     #[test]
     fn test_detect_os() {
@@ -110,10 +633,171 @@ mod tests {
         assert!(!shell.arg.is_empty());
     }
 
+    #[test]
+    fn test_known_shell() {
+        let shell = known_shell("-bash").unwrap();
+        assert_eq!((shell.name.as_str(), shell.cmd.as_str()), ("bash", "bash"));
+        let shell = known_shell("/usr/local/bin/nu").unwrap();
+        assert_eq!((shell.name.as_str(), shell.cmd.as_str()), ("nushell", "nu"));
+        for name in ["xonsh", "elvish", "tcsh", "csh"] {
+            assert_eq!(known_shell(name).unwrap().name, name);
+        }
+        assert_eq!(known_shell("/bin/dash").unwrap().name, "sh");
+        assert!(known_shell("cargo").is_none());
+        assert!(known_shell("python3").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_with() {
+        let edited = edit_with("sed -i s/ls/exa/", "ls -la\ncd /tmp\n").unwrap();
+        assert_eq!(edited, "exa -la\ncd /tmp");
+        assert!(edit_with("false", "ls").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_command() {
+        let output = SHELL
+            .capture_command("echo out; echo err >&2; exit 3")
+            .unwrap();
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.stderr.as_deref(), Some("err\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tee_command() {
+        let output = SHELL
+            .tee_command("echo out; echo err >&2; exit 3", "")
+            .unwrap();
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.stdout.as_deref(), Some("out\n"));
+        assert_eq!(output.stderr.as_deref(), Some("err\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_syntax_error() {
+        assert_eq!(syntax_error("sh", "echo 'a' && ls\n"), None);
+        let error = syntax_error("sh", "if true; then echo 'a\n").unwrap();
+        assert!(!error.is_empty());
+        assert_eq!(syntax_error("powershell", "Get-ChildItem {"), None);
+    }
+
+    #[test]
+    fn test_tee() {
+        let input = "x".repeat(10 * MAX_OUTPUT_LEN) + "end";
+        let mut copy = Vec::new();
+        let kept = tee(input.as_bytes(), &mut copy);
+        assert_eq!(copy, input.as_bytes());
+        assert_eq!(kept.len(), 4 * MAX_OUTPUT_LEN);
+        assert!(kept.ends_with(b"xend"));
+    }
+
+    #[test]
+    fn test_collect_context() {
+        assert_eq!(collect_context(&[]), None);
+
+        let context = collect_context(&[ContextItem::Cwd]).unwrap();
+        assert!(context.cwd.is_some());
+        assert!(context.git_branch.is_none());
+        assert!(context.tools.is_empty());
+        assert!(context.files.is_empty());
+    }
+
+    #[test]
+    fn test_list_dir() {
+        let dir = env::temp_dir().join(format!("shc-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        for name in ["b.txt", "a.txt", ".env"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            list_dir(&dir, 10),
+            (vec!["a.txt".into(), "b.txt".into(), "src/".into()], 0)
+        );
+        assert_eq!(list_dir(&dir, 1), (vec!["a.txt".into()], 2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_executables() {
+        assert_eq!(
+            executables(
+                "cd /tmp && sudo -E FOO=1 rg -l foo | xargs -0 sed -i 's/a|b/c/'; echo done"
+            ),
+            ["rg", "sed"]
+        );
+        assert_eq!(
+            executables(
+                "for f in *.txt; do jq . \"$f\"; done\nif [ -x ./run.sh ]; then ./run.sh; fi"
+            ),
+            ["jq"]
+        );
+        assert_eq!(
+            executables("LANG=C $(which fd) x && (cd src; git log) | `tr a b`"),
+            ["which", "git", "tr"]
+        );
+        assert!(executables("$EDITOR file && export A=1").is_empty());
+        assert_eq!(
+            executables("sudo -u postgres psql; nice -n 10 make && env -u DEBUG cargo test"),
+            ["psql", "make", "cargo"]
+        );
+        assert_eq!(
+            executables(
+                "sudo --user=postgres -i psql; timeout -s KILL 5s curl x | xargs -I {} rm {}"
+            ),
+            ["psql", "curl", "rm"]
+        );
+        assert_eq!(
+            executables("string split , $argv; and math 1 + 2; or not test -f x; print -l a"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_install_command() {
+        let missing = vec!["rg".to_string(), "fd".to_string()];
+        assert_eq!(
+            install_command("apt", &missing),
+            "sudo apt install ripgrep fd-find"
+        );
+        assert_eq!(install_command("brew", &missing), "brew install ripgrep fd");
+        assert_eq!(
+            install_command("nix-env", &missing),
+            "nix-env -iA nixpkgs.ripgrep nixpkgs.fd"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_executable() {
+        assert!(find_executable("sh").is_some());
+        assert!(find_executable("surely-not-installed-shc").is_none());
+    }
+
     #[test]
     fn test_run_command() {
-        let result = SHELL.run_command("echo Hello, world!");
+        let path = env::temp_dir().join(format!("shc-history-{}", uuid::Uuid::new_v4()));
+        let shell = Shell {
+            history_file: Some(path.clone()),
+            ..Shell::new(&SHELL.name, &SHELL.cmd, &SHELL.arg)
+        };
+        std::fs::write(&path, "").unwrap();
+        let command = "echo \"Hello, world!\"";
+        let result = shell.run_command(command, command);
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 0);
+        assert_eq!(shell.run_command("exit 3", "exit 3").unwrap(), 3);
+        let result = shell.run_command("true secret", "true ****");
+        assert_eq!(result.unwrap(), 0);
+
+        let history = std::fs::read(&path).unwrap();
+        assert_eq!(
+            shell_history::parse_history(&shell.name, &history),
+            vec!["echo \"Hello, world!\"", "true ****"]
+        );
+        std::fs::remove_file(&path).unwrap();
     }
-}
\ No newline at end of file
+}
@@ -2,14 +2,229 @@ use serde::{Deserialize, Serialize};
 
 pub const MAX_OS_SHELL_LEN: usize = 20;
 
+pub const MAX_REQUEST_ID_LEN: usize = 64;
+
+pub const MAX_CANDIDATES: usize = 5;
+
+/// Characters of captured command output sent to the server, the end is kept
+pub const MAX_OUTPUT_LEN: usize = 8000;
+
+/// Entries of each context list, e.g. files of the directory, passed to the provider
+pub const MAX_CONTEXT_ENTRIES: usize = 50;
+
+/// Characters of each context value passed to the provider
+pub const MAX_CONTEXT_VALUE_LEN: usize = 256;
+
 pub const HEADER_API_KEY: &str = "api-key";
 
+pub const HEADER_REQUEST_ID: &str = "x-request-id";
+
 #[derive(Serialize, Deserialize)]
 pub struct Question {
     pub os: String,
     pub shell: String,
     pub prompt: String,
     pub explain: bool,
+    /// Continues the conversation of a previous answer to refine its command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Number of alternative commands to choose from
+    #[serde(default = "default_candidates")]
+    pub candidates: usize,
+    #[serde(default)]
+    pub mode: Mode,
+    /// The executed command and what it returned, e.g. to fix it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<CommandOutput>,
+    /// Details of the local environment the user opted in to share
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Context>,
+    /// The language of explanations, e.g. `German`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+impl Question {
+    /// The `explain` flag of older clients wins over the mode
+    pub fn mode(&self) -> Mode {
+        if self.explain {
+            Mode::Explain
+        } else {
+            self.mode
+        }
+    }
+}
+
+fn default_candidates() -> usize {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Generate a command for the prompt
+    #[default]
+    Command,
+    /// Explain the command in the prompt
+    Explain,
+    /// Correct the failed command of the output, the prompt may add details
+    Fix,
+    /// A read-only variant of the command in the prompt, showing what it would change
+    Preview,
+    /// Summarize the output of the command, or explain its error if it failed
+    Summarize,
+    /// Split the task of the prompt into steps to run one by one
+    Plan,
+    /// Write a complete, commented script for the task of the prompt
+    Script,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CommandOutput {
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Context {
+    /// The current working directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// The checked out branch, if the directory is in a git repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+    /// Well-known tools found in the `PATH`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_manager: Option<String>,
+    /// Entries of the current directory, directories end with `/`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// Number of further entries left out of `files`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub more_files: usize,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+impl Context {
+    /// One line per known detail, limited in size as it is sent to the provider.
+    pub fn describe(&self) -> String {
+        let value = |value: &str| {
+            value
+                .chars()
+                .take(MAX_CONTEXT_VALUE_LEN)
+                .collect::<String>()
+        };
+        let list = |values: &[String]| {
+            values
+                .iter()
+                .take(MAX_CONTEXT_ENTRIES)
+                .map(|v| value(v))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut lines = Vec::new();
+        if let Some(cwd) = &self.cwd {
+            lines.push(format!("Current directory: {}", value(cwd)));
+        }
+        if let Some(branch) = &self.git_branch {
+            lines.push(format!("Git repository on branch: {}", value(branch)));
+        }
+        if !self.tools.is_empty() {
+            lines.push(format!("Installed tools: {}", list(&self.tools)));
+        }
+        if let Some(package_manager) = &self.package_manager {
+            lines.push(format!("Package manager: {}", value(package_manager)));
+        }
+        if !self.files.is_empty() {
+            let more = self.more_files + self.files.len().saturating_sub(MAX_CONTEXT_ENTRIES);
+            let mut files = format!("Files in the current directory: {}", list(&self.files));
+            if more > 0 {
+                files.push_str(&format!(" and {} more", more));
+            }
+            lines.push(files);
+        }
+        lines.join("\n")
+    }
+}
+
+/// The last `max_chars` characters, where error messages usually are.
+pub fn tail(text: &str, max_chars: usize) -> &str {
+    let count = text.chars().count();
+    if count <= max_chars {
+        return text;
+    }
+    let start = text
+        .char_indices()
+        .nth(count - max_chars)
+        .map(|(index, _)| index)
+        .unwrap_or(text.len());
+    &text[start..]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    #[default]
+    Low,
+    Medium,
+    High,
+    Denied,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Risk {
+    pub level: RiskLevel,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Answer {
+    /// The generated command, or the explanation in explain mode
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<Risk>,
+    /// Identifies the conversation to send a refinement of the command in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// All distinct commands, the first one is also the answer text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<Candidate>,
+    /// The steps of a plan in their order, the answer text has all their commands
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Candidate>,
+    /// Set by the client from the response header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Candidate {
+    pub text: String,
+    /// A short one-line explanation of the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<Risk>,
+}
+
+/// A command the user edited before using it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Feedback {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub original: String,
+    pub edited: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -26,6 +241,24 @@ pub fn check_or_truncate_max_os_shell(value: &str) -> &str {
     }
 }
 
+pub fn new_request_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Accepts a client supplied request ID only if it is safe to put into logs and headers.
+pub fn check_request_id(value: &str) -> Option<&str> {
+    let valid = !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Some(value)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,6 +271,12 @@ mod tests {
             shell: "bash".to_string(),
             prompt: "What is Rust?".to_string(),
             explain: false,
+            session_id: None,
+            candidates: 1,
+            mode: Mode::Command,
+            output: None,
+            context: None,
+            language: None,
         };
 
         let json = serde_json::to_string(&question).unwrap();
@@ -47,5 +286,104 @@ mod tests {
         assert_eq!(question.shell, deserialized_question.shell);
         assert_eq!(question.prompt, deserialized_question.prompt);
         assert_eq!(question.explain, deserialized_question.explain);
+        assert!(!json.contains("session_id"));
+
+        let deserialized_question: Question =
+            serde_json::from_str(r#"{"os":"Linux","shell":"bash","prompt":"ls","explain":false}"#)
+                .unwrap();
+        assert_eq!(deserialized_question.candidates, 1);
+    }
+
+    #[test]
+    fn test_answer_serialization() {
+        let answer = Answer {
+            text: "rm -rf build".to_string(),
+            risk: Some(Risk {
+                level: RiskLevel::High,
+                reasons: vec!["deletes files recursively".to_string()],
+            }),
+            session_id: None,
+            candidates: vec![],
+            steps: vec![],
+            request_id: None,
+        };
+
+        let json = serde_json::to_string(&answer).unwrap();
+        assert!(json.contains(r#""level":"high""#));
+        let deserialized_answer: Answer = serde_json::from_str(&json).unwrap();
+        assert_eq!(answer.text, deserialized_answer.text);
+        assert_eq!(answer.risk, deserialized_answer.risk);
+
+        let deserialized_answer: Answer = serde_json::from_str(r#"{"text":"ls"}"#).unwrap();
+        assert_eq!(deserialized_answer.risk, None);
+        assert!(!json.contains("candidates"));
+        assert!(RiskLevel::Denied > RiskLevel::High);
+    }
+
+    #[test]
+    fn test_question_mode() {
+        let mut question: Question =
+            serde_json::from_str(r#"{"os":"Linux","shell":"bash","prompt":"ls","explain":true}"#)
+                .unwrap();
+        assert_eq!(question.mode, Mode::Command);
+        assert_eq!(question.mode(), Mode::Explain);
+        question.explain = false;
+        question.mode = Mode::Fix;
+        assert!(serde_json::to_string(&question)
+            .unwrap()
+            .contains(r#""mode":"fix""#));
+        assert_eq!(question.mode(), Mode::Fix);
+        question.mode = Mode::Preview;
+        assert!(serde_json::to_string(&question)
+            .unwrap()
+            .contains(r#""mode":"preview""#));
+    }
+
+    #[test]
+    fn test_context_describe() {
+        assert_eq!(Context::default().describe(), "");
+
+        let context = Context {
+            cwd: Some("/home/user/project".to_string()),
+            git_branch: Some("main".to_string()),
+            tools: vec!["docker".to_string(), "jq".to_string()],
+            package_manager: Some("apt".to_string()),
+            files: vec!["Cargo.toml".to_string(), "src/".to_string()],
+            more_files: 3,
+        };
+        assert_eq!(
+            context.describe(),
+            "Current directory: /home/user/project\n\
+             Git repository on branch: main\n\
+             Installed tools: docker, jq\n\
+             Package manager: apt\n\
+             Files in the current directory: Cargo.toml, src/ and 3 more"
+        );
+
+        let context = Context {
+            files: (0..MAX_CONTEXT_ENTRIES + 2)
+                .map(|i| i.to_string())
+                .collect(),
+            ..Context::default()
+        };
+        assert!(context.describe().ends_with(" and 2 more"));
+    }
+
+    #[test]
+    fn test_tail() {
+        assert_eq!(tail("hello", 10), "hello");
+        assert_eq!(tail("hello", 3), "llo");
+        assert_eq!(tail("grüße", 3), "üße");
+        assert_eq!(tail("hello", 0), "");
+    }
+
+    #[test]
+    fn test_check_request_id() {
+        let request_id = new_request_id();
+        assert_eq!(check_request_id(&request_id), Some(request_id.as_str()));
+        assert_eq!(check_request_id("abc_123-DEF"), Some("abc_123-DEF"));
+        assert_eq!(check_request_id(""), None);
+        assert_eq!(check_request_id("id\ninjected"), None);
+        assert_eq!(check_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)), None);
     }
 }
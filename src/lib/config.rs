@@ -0,0 +1,197 @@
+use crate::command::ContextItem;
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The client configuration file with the named server profiles, e.g.
+///
+/// ```toml
+/// default_profile = "team"
+///
+/// [profiles.team]
+/// url = "https://shellchat.example.com"
+/// key = "secret"
+/// language = "German"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ClientConfig {
+    /// The profile used without `--profile`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// Whether generated commands are recorded for `shc history`, by default they are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<bool>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings of a server profile, the command line options and environment variables win over them.
+#[derive(Args, Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    /// The server URL
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// The language of explanations, e.g. `German`
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Number of alternative commands to choose from
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidates: Option<usize>,
+    /// Details of the local environment to send along
+    #[clap(long, value_delimiter = ',')]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Vec<ContextItem>>,
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<String>,
+    #[clap(long, requires = "client_key")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    #[clap(long, requires = "client_cert")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    /// Regular expressions of simple commands to execute without the risk checks, e.g. `^rm -rf \./target$`
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<String>>,
+    /// Regular expressions of commands never to execute
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deny: Option<Vec<String>>,
+}
+
+/// `$XDG_CONFIG_HOME/shellchat/config.toml`, by default in `~/.config`.
+pub fn config_file() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_dir.join("shellchat").join("config.toml"))
+}
+
+impl ClientConfig {
+    /// Reads the configuration, a missing file is an empty configuration.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(ClientConfig::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Writes the configuration, only readable by the user as it may contain API keys.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    /// The given profile or else the default one, if any.
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<&Profile>> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| anyhow!("the profile {} is not configured", name)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn select(&mut self, name: &str) -> Result<()> {
+        if !self.profiles.contains_key(name) {
+            return Err(anyhow!("the profile {} is not configured", name));
+        }
+        self.default_profile = Some(name.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG_CONTENT: &str = r#"
+        default_profile = "team"
+
+        [profiles.team]
+        url = "https://shellchat.example.com"
+        key = "secret"
+        language = "German"
+        context = ["cwd", "package-manager"]
+
+        [profiles.ollama]
+        url = "http://127.0.0.1:8080"
+        candidates = 3
+        allow = ['^rm -rf \./target$']
+        deny = ['\bterraform\s+destroy\b']
+        "#;
+
+    #[test]
+    fn test_profile() {
+        let config: ClientConfig = toml::from_str(CONFIG_CONTENT).unwrap();
+        assert_eq!(config.history, None);
+
+        let team = config.profile(None).unwrap().unwrap();
+        assert_eq!(team.url.as_deref(), Some("https://shellchat.example.com"));
+        assert_eq!(team.language.as_deref(), Some("German"));
+        assert_eq!(
+            team.context,
+            Some(vec![ContextItem::Cwd, ContextItem::PackageManager])
+        );
+
+        let ollama = config.profile(Some("ollama")).unwrap().unwrap();
+        assert_eq!(ollama.candidates, Some(3));
+        assert_eq!(ollama.key, None);
+        assert_eq!(ollama.allow, Some(vec![r"^rm -rf \./target$".to_string()]));
+        assert_eq!(
+            ollama.deny,
+            Some(vec![r"\bterraform\s+destroy\b".to_string()])
+        );
+
+        assert!(config.profile(Some("staging")).is_err());
+        assert_eq!(ClientConfig::default().profile(None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = env::temp_dir().join(format!("shc-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("shellchat").join("config.toml");
+        assert_eq!(ClientConfig::load(&path).unwrap(), ClientConfig::default());
+
+        let mut config: ClientConfig = toml::from_str(CONFIG_CONTENT).unwrap();
+        assert!(config.select("staging").is_err());
+        config.select("ollama").unwrap();
+        config.save(&path).unwrap();
+        assert_eq!(ClientConfig::load(&path).unwrap(), config);
+
+        fs::write(&path, "profiles = 1").unwrap();
+        assert!(ClientConfig::load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,2 @@
 pub const DEFAULT_API_KEY: &str = "$ecret-key";
+pub const DEFAULT_URL: &str = "http://127.0.0.1:8080";
@@ -0,0 +1,167 @@
+use crate::common::Risk;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// A generated command and what became of it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// RFC 3339 UTC time
+    pub timestamp: String,
+    pub prompt: String,
+    pub command: String,
+    pub executed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<Risk>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl HistoryEntry {
+    /// A command generated now in the current directory, not executed yet.
+    pub fn new(prompt: &str, command: &str) -> Self {
+        HistoryEntry {
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            prompt: prompt.to_string(),
+            command: command.to_string(),
+            executed: false,
+            exit_code: None,
+            cwd: env::current_dir().ok().map(|cwd| cwd.display().to_string()),
+            profile: None,
+            risk: None,
+            request_id: None,
+        }
+    }
+
+    /// Whether all words of the query are in the prompt or the command, ignoring the case.
+    pub fn matches(&self, query: &str) -> bool {
+        let prompt = self.prompt.to_lowercase();
+        let command = self.command.to_lowercase();
+        query
+            .to_lowercase()
+            .split_whitespace()
+            .all(|word| prompt.contains(word) || command.contains(word))
+    }
+}
+
+/// `$XDG_DATA_HOME/shellchat/history.jsonl`, by default in `~/.local/share`.
+pub fn history_file() -> Option<PathBuf> {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))?;
+    Some(data_dir.join("shellchat").join("history.jsonl"))
+}
+
+/// The generated commands, one JSON object per line in the order they were generated.
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: &Path) -> Self {
+        History {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the entry, the file is only readable by the user as prompts may contain secrets.
+    pub fn append(&self, entry: &HistoryEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// All entries, oldest first; lines that can't be read are skipped.
+    pub fn load(&self) -> Result<Vec<HistoryEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// The entry with the 1-based number shown by the search.
+    pub fn get(&self, number: usize) -> Result<Option<HistoryEntry>> {
+        let entries = self.load()?;
+        Ok(number
+            .checked_sub(1)
+            .and_then(|index| entries.into_iter().nth(index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let entry = HistoryEntry::new("list the largest files", "du -ah . | sort -rh | head");
+        assert!(entry.matches("LARGEST"));
+        assert!(entry.matches("files sort"));
+        assert!(entry.matches(""));
+        assert!(!entry.matches("largest pods"));
+    }
+
+    #[test]
+    fn test_append_and_load() {
+        let dir = env::temp_dir().join(format!("shc-{}", uuid::Uuid::new_v4()));
+        let history = History::new(&dir.join("shellchat").join("history.jsonl"));
+        assert!(history.load().unwrap().is_empty());
+
+        let mut entry = HistoryEntry::new("list files", "ls");
+        history.append(&entry).unwrap();
+        entry.executed = true;
+        entry.exit_code = Some(0);
+        history.append(&entry).unwrap();
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(history.path())
+            .unwrap();
+        writeln!(file, "not json").unwrap();
+
+        let entries = history.load().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries[0].executed);
+        assert_eq!(history.get(2).unwrap(), Some(entry));
+        assert_eq!(history.get(0).unwrap(), None);
+        assert_eq!(history.get(3).unwrap(), None);
+        assert!(OffsetDateTime::parse(&entries[0].timestamp, &Rfc3339).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
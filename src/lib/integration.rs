@@ -0,0 +1,55 @@
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum IntegrationShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// The script binding Ctrl-G to replace the prompt line by the generated command,
+/// using the `--print` mode of `shc`, and recording the last command for `shc fix`.
+pub fn init_script(shell: IntegrationShell) -> &'static str {
+    match shell {
+        IntegrationShell::Bash => include_str!("../../shell/shc.bash"),
+        IntegrationShell::Zsh => include_str!("../../shell/shc.zsh"),
+        IntegrationShell::Fish => include_str!("../../shell/shc.fish"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_script() {
+        for shell in [
+            IntegrationShell::Bash,
+            IntegrationShell::Zsh,
+            IntegrationShell::Fish,
+        ] {
+            let script = init_script(shell);
+            assert!(script.contains("_shc_widget"));
+            assert!(script.contains("shc --print"));
+            assert!(!script.contains("2>&1"));
+            assert!(script.contains("--last="));
+            assert!(script.contains("--exit-code="));
+            assert!(!script.contains("export"));
+            assert!(!script.contains("-gx"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bash_init_script_syntax() {
+        let status = std::process::Command::new("bash")
+            .arg("-n")
+            .arg("-c")
+            .arg(init_script(IntegrationShell::Bash))
+            .status();
+        // Only checked where bash is installed
+        if let Ok(status) = status {
+            assert!(status.success());
+        }
+    }
+}
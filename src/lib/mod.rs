@@ -1,10 +1,24 @@
 pub mod client;
 
+pub mod config;
+
+pub mod history;
+
+pub mod integration;
+
 pub mod server;
+
+pub mod session;
+
+pub mod tls;
 pub mod tracing;
 
+pub mod rolling;
+
 pub mod command;
 
+pub mod shell_history;
+
 pub mod prompts;
 
 pub mod providers;
@@ -18,3 +32,13 @@ pub mod chatter;
 pub mod defaults;
 
 pub mod notifier;
+
+pub mod redaction;
+
+pub mod placeholder;
+
+pub mod policy;
+
+pub mod risk;
+
+pub mod sandbox;
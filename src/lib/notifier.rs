@@ -1,4 +1,5 @@
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::HttpMessage;
 use futures::future::{ok, Ready};
 use log::debug;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
@@ -17,6 +18,32 @@ pub struct NotifierConfig {
     pub headers: Option<HashMap<String, String>>,
 }
 
+/// Details of a handled chat request, available as `{request_id}`, `{os}`, `{shell}` and
+/// `{prompt}` placeholders in the notifier body. The prompt is already redacted.
+#[derive(Debug, Clone)]
+pub struct NotifyEvent {
+    pub request_id: String,
+    pub os: String,
+    pub shell: String,
+    pub prompt: String,
+}
+
+impl NotifyEvent {
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{request_id}", &escape(&self.request_id))
+            .replace("{os}", &escape(&self.os))
+            .replace("{shell}", &escape(&self.shell))
+            .replace("{prompt}", &escape(&self.prompt))
+    }
+}
+
+/// Escapes a value to be embedded into a JSON string of the body template.
+fn escape(value: &str) -> String {
+    let quoted = serde_json::Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
 pub struct RequestNotifier {
     config: NotifierConfig,
     client: Arc<Client>,
@@ -74,43 +101,72 @@ where
         let config = self.config.clone();
         let client = self.client.clone();
 
-        actix_rt::spawn(async move {
-            let mut request = client.post(&config.url);
-
-            if let Some(headers) = &config.headers {
-                let mut header_map = HeaderMap::new();
-                for (key, value) in headers {
-                    header_map.insert(
-                        HeaderName::from_bytes(key.as_bytes()).unwrap(),
-                        HeaderValue::from_str(value).unwrap(),
-                    );
-                }
-                request = request.headers(header_map);
-            }
-
-            if let Some(body) = &config.body {
-                request = request.body(body.clone());
-            }
-
-            let response = request.send().await;
-
-            match response {
-                Ok(res) => {
-                    if res.status().is_success() {
-                        debug!("Successfully sent log to webhook");
-                    } else {
-                        debug!("Failed to send log to webhook: {:?}", res.status());
-                    }
-                }
-                Err(e) => {
-                    debug!("Error sending log to webhook: {:?}", e);
-                }
-            }
-        });
-
         Box::pin(async move {
             let res = fut.await?;
+            let event = res.request().extensions().get::<NotifyEvent>().cloned();
+            actix_rt::spawn(async move {
+                notify(&config, &client, event.as_ref()).await;
+            });
             Ok(res)
         })
     }
 }
+
+async fn notify(config: &NotifierConfig, client: &Client, event: Option<&NotifyEvent>) {
+    let mut request = client.post(&config.url);
+
+    if let Some(headers) = &config.headers {
+        let mut header_map = HeaderMap::new();
+        for (key, value) in headers {
+            header_map.insert(
+                HeaderName::from_bytes(key.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        request = request.headers(header_map);
+    }
+
+    if let Some(body) = &config.body {
+        let body = match event {
+            Some(event) => event.render(body),
+            None => body.clone(),
+        };
+        request = request.body(body);
+    }
+
+    let response = request.send().await;
+
+    match response {
+        Ok(res) => {
+            if res.status().is_success() {
+                debug!("Successfully sent log to webhook");
+            } else {
+                debug!("Failed to send log to webhook: {:?}", res.status());
+            }
+        }
+        Err(e) => {
+            debug!("Error sending log to webhook: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify_event_render() {
+        let event = NotifyEvent {
+            request_id: "42".to_string(),
+            os: "linux".to_string(),
+            shell: "bash".to_string(),
+            prompt: "find \"<REDACTED_EMAIL_1>\"".to_string(),
+        };
+        let body = event.render(r#"{"text": "{request_id} {os}/{shell}: {prompt}"}"#);
+        assert_eq!(
+            body,
+            r#"{"text": "42 linux/bash: find \"<REDACTED_EMAIL_1>\""}"#
+        );
+        assert!(serde_json::from_str::<serde_json::Value>(&body).is_ok());
+    }
+}
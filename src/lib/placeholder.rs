@@ -0,0 +1,277 @@
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use std::env;
+use std::ops::Range;
+
+lazy_static! {
+    /// `<your_access_token>` or `<namespace>`, but not redirections, heredocs or `<(cmd)`
+    static ref ANGLE_RE: Regex =
+        Regex::new(r"(?<![<\w])<([A-Za-z][\w.-]*(?: [\w.-]+){0,3})>(?![>\w])").unwrap();
+    /// `your_access_token`, `MY_API_KEY` or `some_project`, but not `$MY_VAR`
+    static ref WORD_RE: Regex =
+        Regex::new(r"(?i)(?<![$\w{-])(?:your|my|some)[_-][a-z0-9][\w-]*").unwrap();
+    static ref SECRET_RE: Regex =
+        Regex::new(r"(?i)token|secret|passw|api[_-]?key|private[_-]?key|credential").unwrap();
+}
+
+const MASK: &str = "****";
+
+/// A value the model could not know and left for the user to fill in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placeholder {
+    /// As in the command, e.g. `<your_access_token>`
+    pub text: String,
+    /// Without the angle brackets, e.g. `your_access_token`
+    pub name: String,
+}
+
+impl Placeholder {
+    /// Whether the value should not be shown, e.g. a token or password.
+    pub fn is_secret(&self) -> bool {
+        SECRET_RE.is_match(&self.name).unwrap_or(false)
+    }
+
+    /// The environment variables that may hold the value, e.g. `YOUR_ACCESS_TOKEN` and `ACCESS_TOKEN`.
+    pub fn env_vars(&self) -> Vec<String> {
+        let var: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut vars = vec![var.clone()];
+        for prefix in ["YOUR_", "MY_", "SOME_"] {
+            if let Some(stripped) = var.strip_prefix(prefix).filter(|s| !s.is_empty()) {
+                vars.push(stripped.to_string());
+            }
+        }
+        vars
+    }
+
+    /// The first of the environment variables which is set, with its value.
+    pub fn env_value(&self) -> Option<(String, String)> {
+        self.env_vars().into_iter().find_map(|var| {
+            let value = env::var(&var).ok().filter(|value| !value.is_empty())?;
+            Some((var, value))
+        })
+    }
+}
+
+/// The distinct placeholders of the command in their order.
+pub fn find_placeholders(command: &str) -> Vec<Placeholder> {
+    let mut found: Vec<(Range<usize>, Placeholder)> = Vec::new();
+    for captures in ANGLE_RE.captures_iter(command).flatten() {
+        if let (Some(whole), Some(name)) = (captures.get(0), captures.get(1)) {
+            found.push((
+                whole.range(),
+                Placeholder {
+                    text: whole.as_str().to_string(),
+                    name: name.as_str().to_string(),
+                },
+            ));
+        }
+    }
+    for word in WORD_RE.find_iter(command).flatten() {
+        let range = word.range();
+        if found
+            .iter()
+            .any(|(other, _)| range.start < other.end && other.start < range.end)
+        {
+            continue;
+        }
+        found.push((
+            range,
+            Placeholder {
+                text: word.as_str().to_string(),
+                name: word.as_str().to_string(),
+            },
+        ));
+    }
+
+    found.sort_by_key(|(range, _)| range.start);
+    let mut placeholders: Vec<Placeholder> = Vec::new();
+    for (_, placeholder) in found {
+        if !placeholders.contains(&placeholder) {
+            placeholders.push(placeholder);
+        }
+    }
+    placeholders
+}
+
+/// The command with each occurrence of the placeholder replaced by the value,
+/// but not where it is part of a longer word like `awesome_host`.
+pub fn fill(command: &str, placeholder: &Placeholder, value: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '$' | '{');
+    let mut filled = String::with_capacity(command.len());
+    let mut last = 0;
+    for (start, text) in command.match_indices(&placeholder.text) {
+        let end = start + text.len();
+        let embedded = command[..start].chars().next_back().is_some_and(is_word)
+            || command[end..].chars().next().is_some_and(is_word);
+        if embedded && !text.starts_with('<') {
+            continue;
+        }
+        filled.push_str(&command[last..start]);
+        filled.push_str(value);
+        last = end;
+    }
+    filled.push_str(&command[last..]);
+    filled
+}
+
+/// The values the user entered for the placeholders of a command.
+#[derive(Debug, Clone, Default)]
+pub struct PlaceholderValues {
+    values: Vec<(Placeholder, String)>,
+}
+
+impl PlaceholderValues {
+    pub fn get(&self, placeholder: &Placeholder) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(known, _)| known == placeholder)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn insert(&mut self, placeholder: Placeholder, value: String) {
+        self.values.retain(|(known, _)| *known != placeholder);
+        self.values.push((placeholder, value));
+    }
+
+    /// The command with the known placeholders replaced by their values.
+    pub fn fill(&self, command: &str) -> String {
+        self.values
+            .iter()
+            .fold(command.to_string(), |filled, (placeholder, value)| {
+                fill(&filled, placeholder, value)
+            })
+    }
+
+    /// The command with the secret values replaced by `****`, to show or record it.
+    pub fn mask(&self, command: &str) -> String {
+        self.replace_secrets(command, |_| MASK)
+    }
+
+    /// The command with the secret values replaced by their placeholders, to send it to the server.
+    pub fn conceal(&self, command: &str) -> String {
+        self.replace_secrets(command, |placeholder| &placeholder.text)
+    }
+
+    fn replace_secrets<'a>(
+        &'a self,
+        command: &str,
+        by: impl Fn(&'a Placeholder) -> &'a str,
+    ) -> String {
+        self.values
+            .iter()
+            .filter(|(placeholder, value)| placeholder.is_secret() && !value.is_empty())
+            .fold(command.to_string(), |replaced, (placeholder, value)| {
+                replaced.replace(value.as_str(), by(placeholder))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(command: &str) -> Vec<String> {
+        find_placeholders(command)
+            .into_iter()
+            .map(|placeholder| placeholder.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_find_placeholders() {
+        assert_eq!(
+            texts(
+                r#"curl -s --header "PRIVATE-TOKEN: <your_access_token>" "https://some_host/api/v4/projects/some_project/pipelines/latest" | jq '.status'"#
+            ),
+            ["<your_access_token>", "some_host", "some_project"]
+        );
+        assert_eq!(
+            texts("kubectl logs <pod name> -n <namespace> && kubectl get pod <pod name>"),
+            ["<pod name>", "<namespace>"]
+        );
+        assert_eq!(texts("export TOKEN=YOUR_API_KEY"), ["YOUR_API_KEY"]);
+        assert_eq!(texts("<your_file>"), ["<your_file>"]);
+    }
+
+    #[test]
+    fn test_find_no_placeholders() {
+        assert!(texts("sort < input.txt > output.txt 2>&1").is_empty());
+        assert!(texts("cat <<EOF\nhi\nEOF").is_empty());
+        assert!(texts("diff <(ls a) <(ls b)").is_empty());
+        assert!(texts(r#"echo "$MY_VAR ${MY_OTHER}" && cd dummy_dir/awesome-app"#).is_empty());
+        assert!(texts("ls -la").is_empty());
+    }
+
+    #[test]
+    fn test_is_secret() {
+        let placeholder = |name: &str| Placeholder {
+            text: format!("<{}>", name),
+            name: name.to_string(),
+        };
+        assert!(placeholder("your_access_token").is_secret());
+        assert!(placeholder("DB_PASSWORD").is_secret());
+        assert!(placeholder("api-key").is_secret());
+        assert!(!placeholder("namespace").is_secret());
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let placeholder = Placeholder {
+            text: "<your-access token>".to_string(),
+            name: "your-access token".to_string(),
+        };
+        assert_eq!(
+            placeholder.env_vars(),
+            ["YOUR_ACCESS_TOKEN", "ACCESS_TOKEN"]
+        );
+
+        let name = format!("SHC_TEST_{}", uuid::Uuid::new_v4().simple()).to_uppercase();
+        let placeholder = Placeholder {
+            text: format!("<my_{}>", name.to_lowercase()),
+            name: format!("my_{}", name.to_lowercase()),
+        };
+        assert_eq!(placeholder.env_value(), None);
+        env::set_var(&name, "value");
+        assert_eq!(
+            placeholder.env_value(),
+            Some((name.clone(), "value".to_string()))
+        );
+        env::remove_var(&name);
+    }
+
+    #[test]
+    fn test_fill_and_mask() {
+        let command = "curl -H 'PRIVATE-TOKEN: <token>' https://some_host/<token>/awesome_host";
+        let placeholders = find_placeholders(command);
+        let filled = fill(command, &placeholders[0], "s3cr3t");
+        let filled = fill(&filled, &placeholders[1], "gitlab.example.com");
+        assert_eq!(
+            filled,
+            "curl -H 'PRIVATE-TOKEN: s3cr3t' https://gitlab.example.com/s3cr3t/awesome_host"
+        );
+
+        let mut values = PlaceholderValues::default();
+        values.insert(placeholders[0].clone(), "s3cr3t".to_string());
+        values.insert(placeholders[1].clone(), "gitlab.example.com".to_string());
+        assert_eq!(values.get(&placeholders[1]), Some("gitlab.example.com"));
+        assert_eq!(values.fill(command), filled);
+        assert_eq!(
+            values.mask(&filled),
+            "curl -H 'PRIVATE-TOKEN: ****' https://gitlab.example.com/****/awesome_host"
+        );
+        assert_eq!(
+            values.conceal(&filled),
+            "curl -H 'PRIVATE-TOKEN: <token>' https://gitlab.example.com/<token>/awesome_host"
+        );
+    }
+}
@@ -1,13 +1,25 @@
-use crate::common::check_or_truncate_max_os_shell;
+use crate::common::{check_or_truncate_max_os_shell, MAX_OS_SHELL_LEN};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Prompts {
     pub explain: String,
+    pub describe: String,
+    pub summarize: String,
+    fix: String,
+    preview: String,
+    plan: String,
+    script: String,
+    context: String,
+    language: String,
     os_prompt: String,
     combinator_powershell: String,
     combinator_default: String,
+    /// Combinators of shells without `&&`, by shell name
+    #[serde(default)]
+    combinators: HashMap<String, String>,
     additional_instructions: String,
 }
 
@@ -23,10 +35,10 @@ impl Prompts {
     }
 
     pub fn shell_prompt(&self, os: &str, shell: &str) -> String {
-        let combinator = if shell == "powershell" {
-            &self.combinator_powershell
-        } else {
-            &self.combinator_default
+        let combinator = match self.combinators.get(shell) {
+            Some(combinator) => combinator,
+            None if shell == "powershell" => &self.combinator_powershell,
+            None => &self.combinator_default,
         };
         format!(
             "{}\n{}\n{}",
@@ -37,24 +49,97 @@ impl Prompts {
             self.additional_instructions
         )
     }
+
+    /// The prompt asking to answer in the language, e.g. to explain a command.
+    pub fn in_language(&self, prompt: &str, language: Option<&str>) -> String {
+        match language
+            .map(str::trim)
+            .filter(|language| !language.is_empty())
+        {
+            Some(language) => {
+                let language = language.chars().take(MAX_OS_SHELL_LEN).collect::<String>();
+                format!(
+                    "{}\n{}",
+                    prompt,
+                    self.language.replace("{language}", &language)
+                )
+            }
+            None => prompt.to_string(),
+        }
+    }
+
+    /// The shell prompt with the details of the user's environment, see `Context::describe`.
+    pub fn shell_prompt_with_context(&self, os: &str, shell: &str, context: &str) -> String {
+        self.with_context(&self.shell_prompt(os, shell), context)
+    }
+
+    /// The prompt with the details of the user's environment, see `Context::describe`.
+    pub fn with_context(&self, prompt: &str, context: &str) -> String {
+        format!("{}\n{}\n{}", prompt, self.context, context)
+    }
+
+    /// The shell prompt with the instructions to correct a failed command.
+    pub fn fix_prompt(&self, os: &str, shell: &str) -> String {
+        format!("{}\n{}", self.shell_prompt(os, shell), self.fix)
+    }
+
+    /// The instructions to split a task into steps, the steps are not combined into one command.
+    pub fn plan_prompt(&self, os: &str, shell: &str) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.os_prompt
+                .replace("{os}", check_or_truncate_max_os_shell(os))
+                .replace("{shell}", check_or_truncate_max_os_shell(shell)),
+            self.additional_instructions,
+            self.plan
+        )
+    }
+
+    /// The instructions to write a whole script instead of a command.
+    pub fn script_prompt(&self, os: &str, shell: &str) -> String {
+        self.script
+            .replace("{os}", check_or_truncate_max_os_shell(os))
+            .replace("{shell}", check_or_truncate_max_os_shell(shell))
+    }
+
+    /// The shell prompt with the instructions for a read-only variant of a command.
+    pub fn preview_prompt(&self, os: &str, shell: &str) -> String {
+        format!("{}\n{}", self.shell_prompt(os, shell), self.preview)
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
-    #[test]
-    fn test_prompt_from_yaml() {
-        let yaml_content = r#"
+    pub(crate) const PROMPTS_CONTENT: &str = r#"
         explain: "Explain prompt"
+        fix: "Fix prompt"
+        preview: "Preview prompt"
+        plan: "Plan prompt"
+        script: "Script prompt for {os} and {shell}"
+        summarize: "Summarize prompt"
+        describe: "Describe prompt"
+        context: "Context prompt"
+        language: "Answer in {language}"
         os_prompt: "Operating system prompt for {os} and {shell}"
         combinator_powershell: "PowerShell combinator"
         combinator_default: "Default combinator"
         additional_instructions: "Additional instructions"
         "#;
 
-        let prompts = Prompts::from_yaml_content(yaml_content);
+    #[test]
+    fn test_prompt_from_yaml() {
+        let prompts = Prompts::from_yaml_content(PROMPTS_CONTENT);
         assert_eq!(prompts.explain, "Explain prompt");
+        assert_eq!(prompts.describe, "Describe prompt");
+        assert_eq!(prompts.fix, "Fix prompt");
+        assert_eq!(prompts.preview, "Preview prompt");
+        assert_eq!(prompts.plan, "Plan prompt");
+        assert_eq!(prompts.script, "Script prompt for {os} and {shell}");
+        assert_eq!(prompts.summarize, "Summarize prompt");
+        assert_eq!(prompts.context, "Context prompt");
+        assert_eq!(prompts.language, "Answer in {language}");
         assert_eq!(
             prompts.os_prompt,
             "Operating system prompt for {os} and {shell}"
@@ -66,15 +151,7 @@ mod tests {
 
     #[test]
     fn test_shell_prompt() {
-        let yaml_content = r#"
-        explain: "Explain prompt"
-        os_prompt: "Operating system prompt for {os} and {shell}"
-        combinator_powershell: "PowerShell combinator"
-        combinator_default: "Default combinator"
-        additional_instructions: "Additional instructions"
-        "#;
-
-        let prompts = Prompts::from_yaml_content(yaml_content);
+        let prompts = Prompts::from_yaml_content(PROMPTS_CONTENT);
 
         let shell_prompt = prompts.shell_prompt("Windows", "powershell");
         assert!(shell_prompt.contains("Operating system prompt for Windows and powershell"));
@@ -86,6 +163,70 @@ mod tests {
         assert!(shell_prompt.contains("Default combinator"));
         assert!(shell_prompt.contains("Additional instructions"));
     }
+
+    #[test]
+    fn test_shell_prompt_combinators() {
+        let mut prompts = Prompts::from_yaml_content(PROMPTS_CONTENT);
+        prompts
+            .combinators
+            .insert("nushell".to_string(), "Nushell combinator".to_string());
+
+        let shell_prompt = prompts.shell_prompt("Linux", "nushell");
+        assert!(shell_prompt.contains("Nushell combinator"));
+        assert!(!shell_prompt.contains("Default combinator"));
+        assert!(prompts
+            .shell_prompt("Linux", "tcsh")
+            .contains("Default combinator"));
+    }
+
+    #[test]
+    fn test_fix_prompt() {
+        let prompts = Prompts::from_yaml_content(PROMPTS_CONTENT);
+        let fix_prompt = prompts.fix_prompt("Linux", "bash");
+        assert!(fix_prompt.starts_with(&prompts.shell_prompt("Linux", "bash")));
+        assert!(fix_prompt.ends_with("Fix prompt"));
+        let plan_prompt = prompts.plan_prompt("Linux", "bash");
+        assert!(plan_prompt.starts_with("Operating system prompt for Linux and bash"));
+        assert!(!plan_prompt.contains("Default combinator"));
+        assert!(plan_prompt.ends_with("Plan prompt"));
+        assert_eq!(
+            prompts.script_prompt("Linux", "zsh"),
+            "Script prompt for Linux and zsh"
+        );
+        let preview_prompt = prompts.preview_prompt("Linux", "bash");
+        assert!(preview_prompt.starts_with(&prompts.shell_prompt("Linux", "bash")));
+        assert!(preview_prompt.ends_with("Preview prompt"));
+    }
+
+    #[test]
+    fn test_shell_prompt_with_context() {
+        let prompts = Prompts::from_yaml_content(PROMPTS_CONTENT);
+        let shell_prompt =
+            prompts.shell_prompt_with_context("Linux", "bash", "Git repository on branch: main");
+        assert!(shell_prompt.starts_with(&prompts.shell_prompt("Linux", "bash")));
+        assert!(shell_prompt.ends_with("Context prompt\nGit repository on branch: main"));
+    }
+
+    #[test]
+    fn test_in_language() {
+        let prompts = Prompts::from_yaml_content(PROMPTS_CONTENT);
+        assert_eq!(
+            prompts.in_language(&prompts.explain, Some("German")),
+            "Explain prompt\nAnswer in German"
+        );
+        assert_eq!(
+            prompts.in_language(&prompts.explain, Some(" ")),
+            "Explain prompt"
+        );
+        assert_eq!(
+            prompts.in_language(&prompts.explain, None),
+            "Explain prompt"
+        );
+        assert!(prompts
+            .in_language(&prompts.explain, Some(&"ü".repeat(100)))
+            .ends_with(&format!("Answer in {}", "ü".repeat(MAX_OS_SHELL_LEN))));
+    }
+
     #[test]
     fn test_prompt_from_yaml_file_not_found() {
         let result = std::panic::catch_unwind(|| Prompts::from_yaml("non_existent_file.yaml"));
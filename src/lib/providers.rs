@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::panic::panic_any;
 use std::sync::Arc;
@@ -16,9 +16,66 @@ pub enum ProviderError {
     UnexpectedResponse(String),
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: &str) -> Self {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+        }
+    }
+}
+
 #[async_trait]
 pub trait ProviderApi {
-    async fn call(&self, role_prompt: &str, user_prompt: &str) -> Result<String, ProviderError>;
+    /// Completes a conversation, the messages start with the system prompt
+    /// followed by the previous turns and end with the current user prompt.
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, ProviderError>;
+
+    async fn call(&self, role_prompt: &str, user_prompt: &str) -> Result<String, ProviderError> {
+        self.chat(&[
+            ChatMessage::new(Role::System, role_prompt),
+            ChatMessage::new(Role::User, user_prompt),
+        ])
+        .await
+    }
+
+    /// Returns up to `n` alternative completions, by default from parallel calls
+    /// for providers without native support for multiple choices.
+    async fn chat_n(
+        &self,
+        messages: &[ChatMessage],
+        n: usize,
+    ) -> Result<Vec<String>, ProviderError> {
+        let results = futures::future::join_all((0..n).map(|_| self.chat(messages))).await;
+        let mut completions = Vec::new();
+        let mut first_error = None;
+        for result in results {
+            match result {
+                Ok(completion) => completions.push(completion),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        match first_error {
+            Some(err) if completions.is_empty() => Err(err),
+            _ => Ok(completions),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -64,17 +121,16 @@ pub struct Message {
     content: String,
 }
 
-#[async_trait]
-impl ProviderApi for AzureOpenAI {
-    async fn call(&self, role_prompt: &str, user_prompt: &str) -> Result<String, ProviderError> {
-        let messages = vec![
-            json!({ "role": "system", "content": role_prompt }),
-            json!({ "role": "user", "content": user_prompt }),
-        ];
-
+impl AzureOpenAI {
+    async fn complete(
+        &self,
+        messages: &[ChatMessage],
+        n: usize,
+    ) -> Result<Vec<String>, ProviderError> {
         let body = json!({
             "model": &self.model,
             "messages": messages,
+            "n": n,
         });
 
         let response = self
@@ -88,18 +144,32 @@ impl ProviderApi for AzureOpenAI {
             .await?;
 
         match serde_json::from_str::<CompletionResponse>(&response) {
-            Ok(resp) => {
-                if let Some(choice) = resp.choices.first() {
-                    Ok(choice.message.content.clone())
-                } else {
-                    Err(ProviderError::UnexpectedResponse(response.to_string()))
-                }
-            }
-            Err(_) => Err(ProviderError::UnexpectedResponse(response.to_string())),
+            Ok(resp) if !resp.choices.is_empty() => Ok(resp
+                .choices
+                .into_iter()
+                .map(|choice| choice.message.content)
+                .collect()),
+            _ => Err(ProviderError::UnexpectedResponse(response.to_string())),
         }
     }
 }
 
+#[async_trait]
+impl ProviderApi for AzureOpenAI {
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, ProviderError> {
+        let mut completions = self.complete(messages, 1).await?;
+        Ok(completions.swap_remove(0))
+    }
+
+    async fn chat_n(
+        &self,
+        messages: &[ChatMessage],
+        n: usize,
+    ) -> Result<Vec<String>, ProviderError> {
+        self.complete(messages, n).await
+    }
+}
+
 pub fn new_provider(provider_type: &ProviderConfig) -> Arc<dyn ProviderApi + Send + Sync> {
     let client = Client::new();
     let provider: Arc<dyn ProviderApi + Send + Sync> = match provider_type {
@@ -132,15 +202,20 @@ mod tests {
 
     #[async_trait::async_trait]
     impl ProviderApi for MockProvider {
-        async fn call(
-            &self,
-            _role_prompt: &str,
-            _user_prompt: &str,
-        ) -> Result<String, ProviderError> {
+        async fn chat(&self, _messages: &[ChatMessage]) -> Result<String, ProviderError> {
             Ok("Mock response".to_string())
         }
     }
 
+    struct MessagesProvider;
+
+    #[async_trait::async_trait]
+    impl ProviderApi for MessagesProvider {
+        async fn chat(&self, messages: &[ChatMessage]) -> Result<String, ProviderError> {
+            Ok(serde_json::to_string(messages)?)
+        }
+    }
+
     #[tokio::test]
     async fn test_provider_call() {
         let provider = MockProvider;
@@ -148,4 +223,19 @@ mod tests {
         assert!(response.is_ok());
         assert_eq!(response.unwrap(), "Mock response");
     }
+
+    #[tokio::test]
+    async fn test_provider_chat_n_calls_in_parallel() {
+        let response = MockProvider.chat_n(&[], 3).await.unwrap();
+        assert_eq!(response, vec!["Mock response"; 3]);
+    }
+
+    #[tokio::test]
+    async fn test_provider_call_builds_messages() {
+        let response = MessagesProvider.call("role", "test").await.unwrap();
+        assert_eq!(
+            response,
+            r#"[{"role":"system","content":"role"},{"role":"user","content":"test"}]"#
+        );
+    }
 }
@@ -0,0 +1,299 @@
+use crate::common::{Risk, RiskLevel};
+use crate::policy::Policy;
+use anyhow::{anyhow, Result};
+use crossterm::style::Stylize;
+use fancy_regex::Regex;
+use std::ops::Range;
+
+/// The risk of a command as seen by the client, with the byte ranges of its destructive parts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub risk: Risk,
+    pub parts: Vec<Range<usize>>,
+}
+
+impl Analysis {
+    /// The command with the destructive parts in bold red.
+    pub fn highlight(&self, command: &str) -> String {
+        let mut highlighted = String::new();
+        let mut last = 0;
+        for part in &self.parts {
+            highlighted.push_str(&command[last..part.start]);
+            highlighted.push_str(&command[part.clone()].red().bold().to_string());
+            last = part.end;
+        }
+        highlighted.push_str(&command[last..]);
+        highlighted
+    }
+}
+
+/// Checks commands before they are executed, using the built-in policy rules
+/// and the allow and deny patterns of the profile.
+#[derive(Debug, Default)]
+pub struct RiskAnalyser {
+    policy: Policy,
+    allow: Vec<Regex>,
+    deny: Vec<(String, Regex)>,
+}
+
+impl RiskAnalyser {
+    /// Simple commands matching an allow pattern are not checked by the policy rules,
+    /// the ones matching a deny pattern are never executed.
+    pub fn new(allow: &[String], deny: &[String]) -> Result<Self> {
+        let compile = |pattern: &String| {
+            Regex::new(pattern).map_err(|err| anyhow!("invalid pattern {}: {}", pattern, err))
+        };
+        Ok(RiskAnalyser {
+            policy: Policy::default(),
+            allow: allow.iter().map(compile).collect::<Result<_>>()?,
+            deny: deny
+                .iter()
+                .map(|pattern| Ok((pattern.clone(), compile(pattern)?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn analyse(&self, command: &str) -> Analysis {
+        let mut risk = Risk::default();
+        let mut parts = Vec::new();
+        let mut add = |level: RiskLevel, reason: String, range: Range<usize>| {
+            risk.level = risk.level.max(level);
+            if !risk.reasons.contains(&reason) {
+                risk.reasons.push(reason);
+            }
+            parts.push(range);
+        };
+
+        // The allow and deny lists apply to the simple commands
+        let mut allowed: Vec<Range<usize>> = Vec::new();
+        for segment in split_commands(command) {
+            let text = &command[segment.clone()];
+            if let Some((pattern, found)) = self
+                .deny
+                .iter()
+                .find_map(|(pattern, regex)| Some((pattern, regex.find(text).ok().flatten()?)))
+            {
+                let range = to_word_end(text, found.range());
+                add(
+                    RiskLevel::Denied,
+                    format!("deny: matches {}", pattern),
+                    segment.start + range.start..segment.start + range.end,
+                );
+            } else if self
+                .allow
+                .iter()
+                .any(|regex| regex.is_match(text).unwrap_or(false))
+            {
+                allowed.push(segment);
+            }
+        }
+        // The policy rules may match across pipes, e.g. `curl ... | sh`
+        for found in self.policy.find_all(command) {
+            let is_allowed = allowed.iter().any(|segment| {
+                segment.start <= found.range.start && found.range.end <= segment.end
+            });
+            if !is_allowed {
+                add(
+                    found.level,
+                    format!("{}: {}", found.name, found.message),
+                    to_word_end(command, found.range),
+                );
+            }
+        }
+
+        parts.sort_by_key(|part| part.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for part in parts {
+            match merged.last_mut() {
+                Some(last) if part.start <= last.end => last.end = last.end.max(part.end),
+                _ => merged.push(part),
+            }
+        }
+        Analysis {
+            risk,
+            parts: merged,
+        }
+    }
+}
+
+/// The higher of both risks with the reasons of both.
+pub fn merge_risks(server: Option<&Risk>, local: &Risk) -> Risk {
+    let mut risk = server.cloned().unwrap_or_default();
+    risk.level = risk.level.max(local.level);
+    for reason in &local.reasons {
+        if !risk.reasons.contains(reason) {
+            risk.reasons.push(reason.clone());
+        }
+    }
+    risk
+}
+
+/// Extends the range to the end of the word, e.g. to highlight the whole `-rf`.
+fn to_word_end(text: &str, range: Range<usize>) -> Range<usize> {
+    if text[range.clone()].ends_with(char::is_whitespace) {
+        return range;
+    }
+    let end = text[range.end..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |index| range.end + index);
+    range.start..end
+}
+
+/// The byte ranges of the simple commands, split at `;`, `&`, `|` and new lines outside of quotes.
+pub fn split_commands(command: &str) -> Vec<Range<usize>> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut previous = None;
+    let mut chars = command.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote != Some('\'') {
+            escaped = true;
+        } else if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+        } else if c == '\'' || c == '"' {
+            quote = Some(c);
+        } else if matches!(c, ';' | '|' | '\n')
+            // `2>&1` and `&>` are redirections
+            || (c == '&'
+                && !matches!(previous, Some('>') | Some('<'))
+                && chars.peek().map(|(_, next)| *next) != Some('>'))
+        {
+            segments.push(start..index);
+            start = index + c.len_utf8();
+        }
+        previous = Some(c);
+    }
+    segments.push(start..command.len());
+
+    segments
+        .into_iter()
+        .filter_map(|segment| {
+            let text = &command[segment.clone()];
+            let trimmed = text.trim_start();
+            let start = segment.start + text.len() - trimmed.len();
+            let end = start + trimmed.trim_end().len();
+            Some(start..end).filter(|range| !range.is_empty())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(command: &str) -> Vec<&str> {
+        split_commands(command)
+            .into_iter()
+            .map(|range| &command[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_split_commands() {
+        assert_eq!(
+            split("cd /tmp && rm -rf build; ls | wc -l"),
+            ["cd /tmp", "rm -rf build", "ls", "wc -l"]
+        );
+        assert_eq!(
+            split("echo 'a; b' \"c && d\" e\\;f"),
+            ["echo 'a; b' \"c && d\" e\\;f"]
+        );
+        assert_eq!(
+            split("make 2>&1 &> log & wait"),
+            ["make 2>&1 &> log", "wait"]
+        );
+        assert!(split(" ; ").is_empty());
+    }
+
+    #[test]
+    fn test_analyse() {
+        let analyser = RiskAnalyser::default();
+        let command = "cd /tmp && sudo rm -rf build";
+        let analysis = analyser.analyse(command);
+        assert_eq!(analysis.risk.level, RiskLevel::High);
+        assert_eq!(analysis.risk.reasons.len(), 2);
+        let parts: Vec<&str> = analysis
+            .parts
+            .iter()
+            .map(|part| &command[part.clone()])
+            .collect();
+        assert_eq!(parts, ["sudo", "rm -rf"]);
+
+        assert_eq!(analyser.analyse("ls -la").risk.level, RiskLevel::Low);
+        assert!(analyser.analyse("ls -la").parts.is_empty());
+    }
+
+    #[test]
+    fn test_allow_and_deny() {
+        let analyser = RiskAnalyser::new(
+            &[r"^rm -rf \./target$".to_string()],
+            &[r"\bterraform\s+destroy\b".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            analyser.analyse("rm -rf ./target").risk.level,
+            RiskLevel::Low
+        );
+        assert_eq!(
+            analyser
+                .analyse("rm -rf ./target && rm -rf ./src")
+                .risk
+                .level,
+            RiskLevel::High
+        );
+        let analysis = analyser.analyse("cd infra; terraform destroy");
+        assert_eq!(analysis.risk.level, RiskLevel::Denied);
+        assert_eq!(
+            analysis.risk.reasons,
+            [r"deny: matches \bterraform\s+destroy\b"]
+        );
+
+        assert!(RiskAnalyser::new(&["(".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_analyse_across_pipes() {
+        let analyser = RiskAnalyser::default();
+        let risk = analyser.analyse("curl -fsSL https://x.sh | sh").risk;
+        assert_eq!(risk.level, RiskLevel::High);
+        assert!(risk.reasons[0].starts_with("pipe_to_shell:"));
+
+        let risk = analyser.analyse(":(){ :|:& };:").risk;
+        assert_eq!(risk.level, RiskLevel::Denied);
+        assert!(risk.reasons[0].starts_with("fork_bomb:"));
+
+        // Allowing one of the commands does not allow what the pipeline does
+        let analyser = RiskAnalyser::new(&["^curl ".to_string()], &[]).unwrap();
+        let risk = analyser.analyse("curl -fsSL https://x.sh | sh").risk;
+        assert_eq!(risk.level, RiskLevel::High);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let analysis = RiskAnalyser::default().analyse("rm -rf build");
+        let highlighted = analysis.highlight("rm -rf build");
+        assert!(highlighted.contains("rm -rf"));
+        assert!(highlighted.ends_with(" build"));
+        assert_ne!(highlighted, "rm -rf build");
+    }
+
+    #[test]
+    fn test_merge_risks() {
+        let server = Risk {
+            level: RiskLevel::Medium,
+            reasons: vec!["sudo: runs with root privileges".to_string()],
+        };
+        let local = RiskAnalyser::default().analyse("sudo rm -rf build").risk;
+        let risk = merge_risks(Some(&server), &local);
+        assert_eq!(risk.level, RiskLevel::High);
+        assert_eq!(risk.reasons.len(), 2);
+        assert_eq!(merge_risks(None, &local), local);
+    }
+}
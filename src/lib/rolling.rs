@@ -0,0 +1,118 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Log file writer that rolls over once the active file reaches `max_bytes`.
+///
+/// The active file is `<dir>/<file_name>`, rolled files are renamed to `<file_name>.1`,
+/// `<file_name>.2`, ... (the higher, the older) and only `max_files` of them are kept.
+pub struct SizeRollingAppender {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl SizeRollingAppender {
+    pub fn new(dir: &str, file_name: &str, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = Path::new(dir).join(file_name);
+        let file = Self::open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(SizeRollingAppender {
+            path,
+            max_bytes,
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn open(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn rolled_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            // Nothing to keep, start the active file from scratch
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rolled_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rolled_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rolled_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rolled_path(1))?;
+            self.file = Self::open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingAppender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.roll()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> String {
+        let dir = std::env::temp_dir().join(format!("shc-rolling-{}", uuid::Uuid::new_v4()));
+        dir.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_size_rolling_appender_rolls_and_retains() {
+        let dir = temp_dir();
+        let mut appender = SizeRollingAppender::new(&dir, "test.log", 10, 2).unwrap();
+
+        for line in ["first-1\n", "second\n", "third-3\n", "fourth\n"] {
+            appender.write_all(line.as_bytes()).unwrap();
+        }
+        appender.flush().unwrap();
+
+        let read = |name: &str| fs::read_to_string(Path::new(&dir).join(name)).unwrap();
+        assert_eq!(read("test.log"), "fourth\n");
+        assert_eq!(read("test.log.1"), "third-3\n");
+        assert_eq!(read("test.log.2"), "second\n");
+        assert!(!Path::new(&dir).join("test.log.3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_size_rolling_appender_continues_existing_file() {
+        let dir = temp_dir();
+        {
+            let mut appender = SizeRollingAppender::new(&dir, "test.log", 100, 1).unwrap();
+            appender.write_all(b"before restart\n").unwrap();
+        }
+        let appender = SizeRollingAppender::new(&dir, "test.log", 100, 1).unwrap();
+        assert_eq!(appender.written, 15);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+use crate::command::find_executable;
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// `--overlay-src` is available since bubblewrap 0.10
+const MIN_BWRAP_VERSION: (u32, u32) = (0, 10);
+/// Created by the command in the sandbox before the shell starts, the status directory
+/// is bound below the empty `/tmp`
+const STATUS_DIR: &str = "/tmp/.shc-status";
+const STARTED: &str = "started";
+
+/// What a command run in the sandbox did to an entry of the working directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Relative to the working directory
+    pub path: PathBuf,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.kind {
+            ChangeKind::Created => '+',
+            ChangeKind::Modified => '~',
+            ChangeKind::Deleted => '-',
+        };
+        write!(f, "{} {}", sign, self.path.display())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxRun {
+    pub exit_code: i32,
+    pub changes: Vec<Change>,
+}
+
+/// Runs commands with bubblewrap in new namespaces without network access: the root
+/// is read-only, `/tmp` is empty and the working directory is a throwaway overlay,
+/// whose upper directory holds the changes after the command finished.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    bwrap: PathBuf,
+}
+
+impl Sandbox {
+    /// The sandbox if bubblewrap is installed in a recent enough version and can create
+    /// namespaces, which user namespaces being disabled prevents. Only available on Linux.
+    pub fn detect() -> Option<Self> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let bwrap = find_executable("bwrap")?;
+        let output = Command::new(&bwrap).arg("--version").output().ok()?;
+        let version = String::from_utf8_lossy(&output.stdout);
+        if parse_version(&version).is_none_or(|version| version < MIN_BWRAP_VERSION) {
+            debug!("No sandbox, {} is too old", version.trim());
+            return None;
+        }
+        let status = Command::new(&bwrap)
+            .args(["--ro-bind", "/", "/", "--unshare-all", "--", "true"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok()?;
+        if !status.success() {
+            debug!("No sandbox, bwrap can't create namespaces: {}", status);
+            return None;
+        }
+        Some(Sandbox { bwrap })
+    }
+
+    /// Runs `<shell> <arg> <command>` in the working directory and discards its changes.
+    pub fn run(&self, shell: &str, arg: &str, command: &str) -> Result<SandboxRun> {
+        let cwd = env::current_dir()?;
+        let dir = env::temp_dir().join(format!("shc-sandbox-{}", uuid::Uuid::new_v4()));
+        let upper = dir.join("upper");
+        let work = dir.join("work");
+        let status_dir = dir.join("status");
+        fs::create_dir_all(&upper)?;
+        fs::create_dir_all(&work)?;
+        fs::create_dir_all(&status_dir)?;
+
+        // The exit code of bwrap is the command's, unless bwrap itself failed before
+        let started = format!(": > {}/{}; exec \"$0\" \"$@\"", STATUS_DIR, STARTED);
+        let status = Command::new(&self.bwrap)
+            .args(bwrap_args(&cwd, &upper, &work))
+            .args([
+                "--bind".as_ref(),
+                status_dir.as_os_str(),
+                STATUS_DIR.as_ref(),
+            ])
+            .args(["--", "sh", "-c", &started])
+            .arg(shell)
+            .arg(arg)
+            .arg(command)
+            .status();
+        let run = match status {
+            Ok(status) if !status_dir.join(STARTED).exists() => Err(anyhow!(
+                "The sandbox failed to start, bwrap exited with {}",
+                status
+            )),
+            Ok(status) => match status.code() {
+                Some(exit_code) => Ok(SandboxRun {
+                    exit_code,
+                    changes: changes(&upper, &cwd)?,
+                }),
+                None => Err(anyhow!("The command in the sandbox was killed: {}", status)),
+            },
+            Err(err) => Err(anyhow!("Failed to start the sandbox: {}", err)),
+        };
+        if let Err(err) = remove_dirs(&dir) {
+            debug!("Failed to remove {}: {}", dir.display(), err);
+        }
+        run
+    }
+}
+
+fn bwrap_args(cwd: &Path, upper: &Path, work: &Path) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+        "--unshare-all",
+        "--die-with-parent",
+        "--overlay-src",
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    args.push(cwd.into());
+    args.push("--overlay".into());
+    args.push(upper.into());
+    args.push(work.into());
+    args.push(cwd.into());
+    args.push("--chdir".into());
+    args.push(cwd.into());
+    args
+}
+
+/// The major and minor version in the output of `bwrap --version`, e.g. `bubblewrap 0.11.0`.
+fn parse_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.split_whitespace().nth(1)?;
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    Some((parts.next()??, parts.next()??))
+}
+
+/// The entries of the overlay's upper directory compared to the original directory,
+/// deleted entries are character devices with the device number 0.
+pub fn changes(upper: &Path, original: &Path) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    collect_changes(upper, original, Path::new(""), &mut changes)?;
+    Ok(changes)
+}
+
+fn collect_changes(
+    upper: &Path,
+    original: &Path,
+    relative: &Path,
+    changes: &mut Vec<Change>,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(upper.join(relative))?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        let metadata = entry.metadata()?;
+        let existing = fs::symlink_metadata(original.join(&path)).ok();
+        if is_whiteout(&metadata) {
+            changes.push(Change {
+                kind: ChangeKind::Deleted,
+                path,
+            });
+        } else if metadata.is_dir() && existing.as_ref().is_some_and(|existing| existing.is_dir()) {
+            collect_changes(upper, original, &path, changes)?;
+        } else {
+            changes.push(Change {
+                kind: if existing.is_some() {
+                    ChangeKind::Modified
+                } else {
+                    ChangeKind::Created
+                },
+                path,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_whiteout(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    metadata.file_type().is_char_device() && metadata.rdev() == 0
+}
+
+#[cfg(not(unix))]
+fn is_whiteout(_: &fs::Metadata) -> bool {
+    false
+}
+
+/// Removes the temporary directories, the kernel leaves an inaccessible directory in the work directory.
+fn remove_dirs(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let inner = dir.join("work").join("work");
+        if inner.exists() {
+            fs::set_permissions(&inner, fs::Permissions::from_mode(0o700))?;
+        }
+    }
+    fs::remove_dir_all(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bwrap_args() {
+        let args = bwrap_args(
+            Path::new("/home/jane/project"),
+            Path::new("/tmp/upper"),
+            Path::new("/tmp/work"),
+        );
+        let args: Vec<&str> = args.iter().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(&args[..3], ["--ro-bind", "/", "/"]);
+        assert!(args.contains(&"--unshare-all"));
+        assert!(args
+            .join(" ")
+            .ends_with("--overlay-src /home/jane/project --overlay /tmp/upper /tmp/work /home/jane/project --chdir /home/jane/project"));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("bubblewrap 0.11.0\n"), Some((0, 11)));
+        assert_eq!(parse_version("bubblewrap 0.8.0"), Some((0, 8)));
+        assert!(parse_version("bubblewrap 0.8.0").unwrap() < MIN_BWRAP_VERSION);
+        assert_eq!(parse_version("bwrap: unknown option"), None);
+    }
+
+    #[test]
+    fn test_changes() {
+        let dir = env::temp_dir().join(format!("shc-{}", uuid::Uuid::new_v4()));
+        let original = dir.join("original");
+        let upper = dir.join("upper");
+        fs::create_dir_all(original.join("src")).unwrap();
+        fs::write(original.join("README.md"), "readme").unwrap();
+        fs::write(original.join("src").join("main.rs"), "main").unwrap();
+        fs::create_dir_all(upper.join("src")).unwrap();
+        fs::create_dir_all(upper.join("build").join("out")).unwrap();
+        fs::write(upper.join("src").join("main.rs"), "changed").unwrap();
+        fs::write(upper.join("src").join("lib.rs"), "lib").unwrap();
+
+        let changes = changes(&upper, &original).unwrap();
+        let lines: Vec<String> = changes.iter().map(|change| change.to_string()).collect();
+        assert_eq!(lines, ["+ build", "+ src/lib.rs", "~ src/main.rs"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
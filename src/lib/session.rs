@@ -0,0 +1,170 @@
+use crate::providers::{ChatMessage, Role};
+use crate::redaction::Redacted;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SessionConfig {
+    /// Seconds of inactivity after which a session is dropped
+    #[serde(default = "default_ttl")]
+    pub ttl: u64,
+    /// Number of previous prompt/command pairs sent to the provider
+    #[serde(default = "default_max_turns")]
+    pub max_turns: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            ttl: default_ttl(),
+            max_turns: default_max_turns(),
+        }
+    }
+}
+
+fn default_ttl() -> u64 {
+    900
+}
+
+fn default_max_turns() -> usize {
+    10
+}
+
+/// The conversation so far. The messages are redacted like everything sent to the provider,
+/// but the secrets they replace are kept in memory until the session expires.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    pub messages: Vec<ChatMessage>,
+    /// The plaintext secrets behind the placeholders of all turns, to restore them in the
+    /// answers and to reuse the same placeholders for the same secrets in later prompts
+    pub redacted: Redacted,
+}
+
+impl Session {
+    pub fn add_turn(&mut self, prompt: &str, answer: &str) {
+        self.messages.push(ChatMessage::new(Role::User, prompt));
+        self.messages
+            .push(ChatMessage::new(Role::Assistant, answer));
+    }
+
+    /// Replaces the latest answer, e.g. by the command as edited by the user.
+    pub fn edit_last_answer(&mut self, answer: &str) {
+        if let Some(message) = self
+            .messages
+            .iter_mut()
+            .rev()
+            .find(|message| message.role == Role::Assistant)
+        {
+            message.content = answer.to_string();
+        }
+    }
+}
+
+/// In-memory conversations of the refinement flow, addressed by a random session ID.
+pub struct SessionStore {
+    config: SessionConfig,
+    sessions: Mutex<HashMap<String, (Session, Instant)>>,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        SessionStore::new(SessionConfig::default())
+    }
+}
+
+impl SessionStore {
+    pub fn new(config: SessionConfig) -> Self {
+        SessionStore {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.config.ttl)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Session> {
+        let sessions = self.sessions.lock().ok()?;
+        sessions
+            .get(id)
+            .filter(|(_, updated)| updated.elapsed() < self.ttl())
+            .map(|(session, _)| session.clone())
+    }
+
+    /// Stores the session under the given ID or a new one and returns the ID.
+    pub fn save(&self, id: Option<&str>, mut session: Session) -> String {
+        let id = id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let max_messages = self.config.max_turns * 2;
+        if session.messages.len() > max_messages {
+            session
+                .messages
+                .drain(..session.messages.len() - max_messages);
+        }
+        if let Ok(mut sessions) = self.sessions.lock() {
+            let ttl = self.ttl();
+            sessions.retain(|_, (_, updated)| updated.elapsed() < ttl);
+            sessions.insert(id.clone(), (session, Instant::now()));
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_get() {
+        let store = SessionStore::default();
+        let mut session = Session::default();
+        session.add_turn("list files", "ls");
+        let id = store.save(None, session);
+
+        let mut session = store.get(&id).unwrap();
+        assert_eq!(session.messages.len(), 2);
+        session.add_turn("with hidden files", "ls -a");
+        assert_eq!(store.save(Some(&id), session), id);
+        assert_eq!(store.get(&id).unwrap().messages.len(), 4);
+        assert!(store.get("unknown").is_none());
+    }
+
+    #[test]
+    fn test_edit_last_answer() {
+        let mut session = Session::default();
+        session.add_turn("list files", "ls");
+        session.add_turn("with hidden files", "ls -a");
+        session.edit_last_answer("ls -A");
+        assert_eq!(session.messages[1].content, "ls");
+        assert_eq!(session.messages[3].content, "ls -A");
+    }
+
+    #[test]
+    fn test_max_turns_and_ttl() {
+        let store = SessionStore::new(SessionConfig {
+            ttl: 0,
+            max_turns: 1,
+        });
+        let mut session = Session::default();
+        session.add_turn("list files", "ls");
+        session.add_turn("with hidden files", "ls -a");
+        let id = store.save(None, session);
+        assert!(store.get(&id).is_none());
+
+        let store = SessionStore::new(SessionConfig {
+            max_turns: 1,
+            ..SessionConfig::default()
+        });
+        let mut session = Session::default();
+        session.add_turn("list files", "ls");
+        session.add_turn("with hidden files", "ls -a");
+        let id = store.save(None, session);
+        let messages = store.get(&id).unwrap().messages;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "ls -a");
+    }
+}
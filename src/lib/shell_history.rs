@@ -0,0 +1,441 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// nushell writes the new lines of a command in its plain text history like this
+const NUSHELL_NEWLINE: &str = "<\\n>";
+
+/// zsh escapes some bytes of the history file, see `unmetafy` in the zsh sources
+const ZSH_META: u8 = 0x83;
+/// The last of the bytes zsh uses as tokens internally
+const ZSH_META_END: u8 = 0xa2;
+
+/// The history file of the shell, `HISTFILE` is only seen if it was exported.
+/// xonsh and elvish keep their history in JSON session files or a database instead.
+pub fn history_file(shell: &str) -> Option<PathBuf> {
+    let home = PathBuf::from(env::var_os("HOME")?);
+    let histfile = env::var_os("HISTFILE").map(PathBuf::from);
+    match shell {
+        "bash" => Some(histfile.unwrap_or_else(|| home.join(".bash_history"))),
+        "zsh" => Some(histfile.unwrap_or_else(|| {
+            let zdotdir = env::var_os("ZDOTDIR").map(PathBuf::from);
+            zdotdir.unwrap_or(home).join(".zsh_history")
+        })),
+        "fish" => {
+            let data_dir = env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".local").join("share"));
+            Some(data_dir.join("fish").join("fish_history"))
+        }
+        "nushell" => {
+            let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+                Some(dir) => PathBuf::from(dir),
+                None if env::consts::OS == "macos" => {
+                    home.join("Library").join("Application Support")
+                }
+                None => home.join(".config"),
+            };
+            Some(config_dir.join("nushell").join("history.txt"))
+        }
+        "tcsh" | "csh" => Some(home.join(".history")),
+        _ => None,
+    }
+}
+
+/// The latest command in the history file of the shell, skipping `shc` itself.
+pub fn last_command(shell: &str) -> Option<String> {
+    let content = fs::read(history_file(shell)?).ok()?;
+    parse_history(shell, &content)
+        .into_iter()
+        .rev()
+        .find(|command| !is_shc(command))
+}
+
+/// Appends the command to the history file in the format of the shell, if the file exists.
+/// A running shell only sees it after reading the file again, e.g. with `history -n` in bash.
+pub fn append_history(path: &Path, shell: &str, command: &str) -> io::Result<()> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    append_to(path, shell, command, time)
+}
+
+fn append_to(path: &Path, shell: &str, command: &str, time: u64) -> io::Result<()> {
+    // The shell may keep its history elsewhere, e.g. with `HISTFILE` not exported
+    // or in `ZDOTDIR`, then a new file would only be a stray one
+    if !path.is_file() {
+        return Ok(());
+    }
+    let content = fs::read(path)?;
+    if shell == "bash" && is_ignored_by_bash(command, &content) {
+        return Ok(());
+    }
+    // bash only writes timestamps with `HISTTIMEFORMAT`, which is rarely exported
+    let timestamps = env::var_os("HISTTIMEFORMAT").is_some()
+        || parse_bash_timestamps(&String::from_utf8_lossy(&content));
+    let mut entry = Vec::new();
+    if content.last().is_some_and(|&byte| byte != b'\n') {
+        entry.push(b'\n');
+    }
+    entry.extend(format_entry(shell, command, time, timestamps));
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&entry)
+}
+
+/// bash leaves out the commands matching `HISTCONTROL` and `HISTIGNORE`, if they were exported.
+fn is_ignored_by_bash(command: &str, content: &[u8]) -> bool {
+    let histcontrol = env::var("HISTCONTROL").unwrap_or_default();
+    let histignore = env::var("HISTIGNORE").unwrap_or_default();
+    is_ignored(command, content, &histcontrol, &histignore)
+}
+
+fn is_ignored(command: &str, content: &[u8], histcontrol: &str, histignore: &str) -> bool {
+    let controls: Vec<&str> = histcontrol.split(':').collect();
+    let has = |control: &str| controls.contains(&control) || controls.contains(&"ignoreboth");
+    let last = || parse_bash(&String::from_utf8_lossy(content)).pop();
+    if has("ignorespace") && command.starts_with(' ') {
+        return true;
+    }
+    if has("ignoredups") && last().is_some_and(|last| last == command.trim_end()) {
+        return true;
+    }
+    histignore
+        .split(':')
+        .filter(|pattern| !pattern.is_empty())
+        .any(|pattern| match pattern {
+            // `&` stands for the previous entry
+            "&" => last().is_some_and(|last| last == command.trim_end()),
+            _ => glob_match(pattern.as_bytes(), command.trim_end().as_bytes()),
+        })
+}
+
+/// Matches the whole text against a pattern with `*`, `?` and `\` escapes.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((b'\\', [escaped, rest @ ..])) => {
+            text.first() == Some(escaped) && glob_match(rest, &text[1..])
+        }
+        Some((byte, rest)) => text.first() == Some(byte) && glob_match(rest, &text[1..]),
+    }
+}
+
+fn parse_bash_timestamps(content: &str) -> bool {
+    content.lines().any(is_bash_timestamp)
+}
+
+fn is_bash_timestamp(line: &str) -> bool {
+    line.len() > 1 && line.starts_with('#') && line[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// The history entry of the command, `timestamps` only applies to bash.
+fn format_entry(shell: &str, command: &str, time: u64, timestamps: bool) -> Vec<u8> {
+    let command = command.trim_end_matches(['\n', '\r']);
+    match shell {
+        "zsh" => {
+            let line = format!(": {}:0;{}\n", time, command.replace('\n', "\\\n"));
+            metafy(line.as_bytes())
+        }
+        "fish" => format!("- cmd: {}\n  when: {}\n", escape_fish(command), time).into_bytes(),
+        "nushell" => format!("{}\n", command.replace('\n', NUSHELL_NEWLINE)).into_bytes(),
+        "tcsh" | "csh" => format!("#+{}\n{}\n", time, command).into_bytes(),
+        _ if timestamps => format!("#{}\n{}\n", time, command).into_bytes(),
+        // Without timestamps bash reads each line back as a command of its own,
+        // with them it keeps the lines up to the next timestamp together
+        _ if command.contains('\n') => Vec::new(),
+        _ => format!("{}\n", command).into_bytes(),
+    }
+}
+
+fn is_shc(command: &str) -> bool {
+    command == "shc" || command.starts_with("shc ")
+}
+
+pub fn parse_history(shell: &str, content: &[u8]) -> Vec<String> {
+    match shell {
+        "zsh" => parse_zsh(content),
+        "fish" => parse_fish(&String::from_utf8_lossy(content)),
+        "nushell" => parse_nushell(&String::from_utf8_lossy(content)),
+        "tcsh" | "csh" => parse_tcsh(&String::from_utf8_lossy(content)),
+        _ => parse_bash(&String::from_utf8_lossy(content)),
+    }
+}
+
+/// One command per line, `#<timestamp>` lines are written with `HISTTIMEFORMAT`.
+fn parse_bash(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .filter(|line| !is_bash_timestamp(line))
+        .map(str::to_string)
+        .collect()
+}
+
+/// One command per line, with `<\n>` for the new lines of multi-line commands.
+fn parse_nushell(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.replace(NUSHELL_NEWLINE, "\n"))
+        .collect()
+}
+
+/// One command per line, each after a `#+<timestamp>` line with `savehist merge`.
+fn parse_tcsh(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter(|line| {
+            !line
+                .strip_prefix("#+")
+                .is_some_and(|time| time.chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Plain or extended (`: <start>:<duration>;<command>`) lines,
+/// multi-line commands continue after a trailing backslash.
+fn parse_zsh(content: &[u8]) -> Vec<String> {
+    let content = String::from_utf8_lossy(&unmetafy(content)).to_string();
+    let mut commands: Vec<String> = Vec::new();
+    let mut continued = false;
+    for line in content.lines() {
+        if continued {
+            if let Some(command) = commands.last_mut() {
+                command.push('\n');
+                command.push_str(line.strip_suffix('\\').unwrap_or(line));
+            }
+        } else {
+            let command = strip_zsh_extended(line);
+            commands.push(command.strip_suffix('\\').unwrap_or(command).to_string());
+        }
+        continued = line.ends_with('\\');
+    }
+    commands.retain(|command| !command.trim().is_empty());
+    commands
+}
+
+fn strip_zsh_extended(line: &str) -> &str {
+    let Some(rest) = line.strip_prefix(": ") else {
+        return line;
+    };
+    match rest.split_once(';') {
+        Some((meta, command))
+            if meta.split(':').count() == 2
+                && meta.chars().all(|c| c.is_ascii_digit() || c == ':') =>
+        {
+            command
+        }
+        _ => line,
+    }
+}
+
+fn metafy(content: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(content.len());
+    for &byte in content {
+        if byte == 0 || (ZSH_META..=ZSH_META_END).contains(&byte) {
+            bytes.push(ZSH_META);
+            bytes.push(byte ^ 0x20);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    bytes
+}
+
+fn unmetafy(content: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(content.len());
+    let mut iter = content.iter();
+    while let Some(&byte) = iter.next() {
+        if byte == ZSH_META {
+            if let Some(&next) = iter.next() {
+                bytes.push(next ^ 0x20);
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+    bytes
+}
+
+/// YAML like entries `- cmd: <command>` with `\\` and `\n` escaped.
+fn parse_fish(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.strip_prefix("- cmd: "))
+        .map(unescape_fish)
+        .collect()
+}
+
+fn escape_fish(command: &str) -> String {
+    command.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_fish(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(next) => unescaped.push(next),
+                None => unescaped.push(c),
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bash() {
+        let content = "ls -la\n#1700000000\ngit status\n\nshc fix\n";
+        assert_eq!(
+            parse_history("bash", content.as_bytes()),
+            vec!["ls -la", "git status", "shc fix"]
+        );
+    }
+
+    #[test]
+    fn test_parse_zsh() {
+        let content =
+            ": 1700000000:0;ls -la\nplain\n: 1700000001:2;for f in *; do\\\necho $f\\\ndone\n";
+        assert_eq!(
+            parse_history("zsh", content.as_bytes()),
+            vec!["ls -la", "plain", "for f in *; do\necho $f\ndone"]
+        );
+
+        // "ü" is 0xc3 0xbc, zsh writes the 0x83..0xa2 byte range escaped
+        let content = [b"echo gr\xc3".as_slice(), &[ZSH_META, 0xbc ^ 0x20], b"n\n"].concat();
+        assert_eq!(parse_history("zsh", &content), vec!["echo grün"]);
+    }
+
+    #[test]
+    fn test_parse_fish() {
+        let content = "- cmd: ls -la\n  when: 1700000000\n- cmd: echo a\\\\b\\nc\n  when: 1700000001\n  paths:\n    - a\n";
+        assert_eq!(
+            parse_history("fish", content.as_bytes()),
+            vec!["ls -la", "echo a\\b\nc"]
+        );
+    }
+
+    const TRICKY_COMMANDS: &[&str] = &[
+        r#"echo "double \"quoted\"" 'single' $HOME `date` $(pwd)"#,
+        r"printf '%s\n' a\\b; echo \\ \t",
+        "grep -E 'a|b' <<< \"grün ä € 😀\" && echo ok",
+        "git commit -m 'ends with a backslash \\ '",
+    ];
+
+    #[test]
+    fn test_format_entry() {
+        assert_eq!(
+            format_entry("bash", "ls -la\n", 1700000000, false),
+            b"ls -la\n"
+        );
+        assert_eq!(
+            format_entry("bash", "ls -la", 1700000000, true),
+            b"#1700000000\nls -la\n"
+        );
+        assert_eq!(
+            format_entry("bash", "for f in *; do\necho $f\ndone", 1700000000, false),
+            b""
+        );
+        assert_eq!(
+            format_entry("bash", "for f in *; do\necho $f\ndone", 1700000000, true),
+            b"#1700000000\nfor f in *; do\necho $f\ndone\n"
+        );
+        assert_eq!(
+            format_entry("zsh", "for f in *; do\necho $f\ndone", 1700000000, false),
+            b": 1700000000:0;for f in *; do\\\necho $f\\\ndone\n"
+        );
+        assert_eq!(
+            format_entry("fish", "echo a\\b\nc", 1700000000, false),
+            b"- cmd: echo a\\\\b\\nc\n  when: 1700000000\n"
+        );
+    }
+
+    #[test]
+    fn test_format_entry_round_trip() {
+        for shell in ["bash", "zsh", "fish", "nushell", "tcsh"] {
+            for command in TRICKY_COMMANDS {
+                let entry = format_entry(shell, command, 1700000000, true);
+                assert_eq!(parse_history(shell, &entry), vec![*command], "{}", shell);
+            }
+        }
+        for shell in ["zsh", "fish", "nushell"] {
+            let command = "cat <<EOF\n\"quoted\" \\n\nEOF";
+            let entry = format_entry(shell, command, 1700000000, false);
+            assert_eq!(parse_history(shell, &entry), vec![command], "{}", shell);
+        }
+    }
+
+    #[test]
+    fn test_append_to() {
+        let path = env::temp_dir().join(format!("shc-history-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, "#1600000000\nls").unwrap();
+        for command in TRICKY_COMMANDS {
+            append_to(&path, "bash", command, 1700000000).unwrap();
+        }
+        let content = fs::read(&path).unwrap();
+        assert!(String::from_utf8_lossy(&content).starts_with("#1600000000\nls\n#1700000000\n"));
+        let mut expected = vec!["ls"];
+        expected.extend(TRICKY_COMMANDS);
+        assert_eq!(parse_history("bash", &content), expected);
+        fs::remove_file(&path).unwrap();
+
+        append_to(&path, "bash", "ls", 1700000000).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let content = b"ls -la\n";
+        assert!(is_ignored(" export TOKEN=x", content, "ignorespace", ""));
+        assert!(is_ignored(" export TOKEN=x", content, "ignoreboth", ""));
+        assert!(!is_ignored(" export TOKEN=x", content, "ignoredups", ""));
+        assert!(is_ignored("ls -la", content, "erasedups:ignoreboth", ""));
+        assert!(!is_ignored("ls -la", content, "", ""));
+        assert!(is_ignored("ls -la", content, "", "&"));
+        assert!(is_ignored("ls", content, "", "ls:cd *"));
+        assert!(is_ignored("cd /tmp", content, "", "ls:cd *"));
+        assert!(!is_ignored("cdx", content, "", "ls:cd *"));
+        assert!(is_ignored("curl -H ****", content, "", "*\\*\\*\\*\\**"));
+        assert!(is_ignored("git st", content, "", "git ??"));
+        assert!(!is_ignored("git status", content, "", "git ??"));
+    }
+
+    #[test]
+    fn test_parse_nushell_and_tcsh() {
+        let content = "ls | where size > 1kb\nif true {<\\n>  echo a<\\n>}\n";
+        assert_eq!(
+            parse_history("nushell", content.as_bytes()),
+            vec!["ls | where size > 1kb", "if true {\n  echo a\n}"]
+        );
+        let content = "#+1700000000\nsetenv EDITOR vim\n#+1700000001\nls -la\n";
+        assert_eq!(
+            parse_history("tcsh", content.as_bytes()),
+            vec!["setenv EDITOR vim", "ls -la"]
+        );
+    }
+
+    #[test]
+    fn test_is_shc() {
+        assert!(is_shc("shc fix"));
+        assert!(is_shc("shc"));
+        assert!(!is_shc("shcx"));
+    }
+}
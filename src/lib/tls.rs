@@ -0,0 +1,234 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info};
+
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// PEM certificate chain, enables TLS together with the key
+    #[clap(long, env = "TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<String>,
+    /// PEM private key of the certificate
+    #[clap(long, env = "TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<String>,
+    /// PEM CA certificates to verify client certificates, enables mutual TLS
+    #[clap(long, env = "TLS_CLIENT_CA", requires = "tls_cert")]
+    pub tls_client_ca: Option<String>,
+}
+
+impl TlsOptions {
+    pub fn server_config(&self) -> Result<Option<ServerConfig>> {
+        let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) else {
+            return Ok(None);
+        };
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.tls_client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(client_ca)? {
+                    roots.add(cert)?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let resolver = CertReloader::new(cert, key)?;
+        Ok(Some(builder.with_cert_resolver(Arc::new(resolver))))
+    }
+}
+
+/// Serves the certificate and reloads it when the certificate or key file changes,
+/// e.g. after a renewal, without restarting the server.
+#[derive(Debug)]
+struct CertReloader {
+    cert_path: String,
+    key_path: String,
+    state: RwLock<CertState>,
+}
+
+#[derive(Debug)]
+struct CertState {
+    key: Arc<CertifiedKey>,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl CertReloader {
+    fn new(cert_path: &str, key_path: &str) -> Result<Self> {
+        Ok(CertReloader {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            state: RwLock::new(CertState {
+                key: Arc::new(load_certified_key(cert_path, key_path)?),
+                modified: modified(cert_path, key_path),
+                checked: Instant::now(),
+            }),
+        })
+    }
+
+    fn reload_if_changed(&self) {
+        // Most handshakes only need the read lock
+        match self.state.read() {
+            Ok(state) if state.checked.elapsed() >= RELOAD_CHECK_INTERVAL => {}
+            _ => return,
+        }
+        let Ok(mut state) = self.state.write() else {
+            return;
+        };
+        // Another handshake may have checked the files while waiting for the lock
+        if state.checked.elapsed() < RELOAD_CHECK_INTERVAL {
+            return;
+        }
+        state.checked = Instant::now();
+
+        let modified = modified(&self.cert_path, &self.key_path);
+        if modified == state.modified {
+            return;
+        }
+        match load_certified_key(&self.cert_path, &self.key_path) {
+            Ok(key) => {
+                info!("Reloaded the TLS certificate {}", self.cert_path);
+                state.key = Arc::new(key);
+                state.modified = modified;
+            }
+            // Keep the current certificate, the files may be in the middle of an update
+            Err(err) => error!("Failed to reload the TLS certificate: {:?}", err),
+        }
+    }
+}
+
+impl ResolvesServerCert for CertReloader {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.reload_if_changed();
+        self.state.read().ok().map(|state| state.key.clone())
+    }
+}
+
+fn modified(cert_path: &str, key_path: &str) -> Option<SystemTime> {
+    let modified = |path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    modified(cert_path).max(modified(key_path))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse the certificates {}", path))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse the private key {}", path))?
+        .ok_or_else(|| anyhow!("No private key found in {}", path))
+}
+
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+    let provider = CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(ring::default_provider()));
+    let signing_key = provider.key_provider.load_private_key(key)?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_config_without_tls() {
+        let options = TlsOptions::default();
+        assert!(options.server_config().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_server_config_missing_files() {
+        let options = TlsOptions {
+            tls_cert: Some("non_existent_cert.pem".to_string()),
+            tls_key: Some("non_existent_key.pem".to_string()),
+            tls_client_ca: None,
+        };
+        let err = options.server_config().unwrap_err();
+        assert!(err.to_string().contains("non_existent_cert.pem"));
+    }
+
+    fn write_self_signed(dir: &std::path::Path) -> (String, String) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        fs::write(&cert_path, cert.cert.pem()).unwrap();
+        fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+        (
+            cert_path.to_str().unwrap().to_string(),
+            key_path.to_str().unwrap().to_string(),
+        )
+    }
+
+    #[test]
+    fn test_server_config_with_mutual_tls() {
+        let dir = std::env::temp_dir().join(format!("shc-tls-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let (cert, key) = write_self_signed(&dir);
+
+        let options = TlsOptions {
+            tls_cert: Some(cert.clone()),
+            tls_key: Some(key),
+            tls_client_ca: Some(cert),
+        };
+        assert!(options.server_config().unwrap().is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cert_reloader_reloads_changed_files() {
+        let dir = std::env::temp_dir().join(format!("shc-tls-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let (cert, key) = write_self_signed(&dir);
+
+        let reloader = CertReloader::new(&cert, &key).unwrap();
+        let first = reloader.state.read().unwrap().key.clone();
+
+        write_self_signed(&dir);
+        {
+            let mut state = reloader.state.write().unwrap();
+            state.checked = Instant::now() - RELOAD_CHECK_INTERVAL;
+            state.modified = Some(SystemTime::UNIX_EPOCH);
+        }
+        reloader.reload_if_changed();
+        let second = reloader.state.read().unwrap().key.clone();
+        assert_ne!(first.cert, second.cert);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_certs_without_certificates() {
+        let path = std::env::temp_dir().join(format!("shc-tls-{}.pem", uuid::Uuid::new_v4()));
+        fs::write(&path, "no pem content").unwrap();
+        let result = load_certs(path.to_str().unwrap());
+        assert!(result.is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
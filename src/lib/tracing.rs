@@ -1,47 +1,240 @@
+use crate::rolling::SizeRollingAppender;
+use clap::{Args, ValueEnum};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use std::io::IsTerminal;
+use tracing::Subscriber;
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_appender::rolling;
-use tracing_subscriber::{fmt, fmt::format::FmtSpan, prelude::*, EnvFilter};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, fmt::format::FmtSpan, prelude::*, EnvFilter, Layer};
 
-pub fn setup_tracing_file_console(dir: &str, file: &str) -> WorkerGuard {
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("INFO"));
+const SERVICE_NAME: &str = "shc-serve";
+const OTLP_TRACES_PATH: &str = "/v1/traces";
+const DEFAULT_LEVEL: &str = "INFO";
+const BYTES_PER_MB: u64 = 1024 * 1024;
 
-    // File logging layer (without color)
-    let file_appender = rolling::hourly(dir, file);
-    let (file_non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
 
-    let file_layer = fmt::layer()
-        .with_writer(file_non_blocking)
-        .with_ansi(false) // Disable ANSI escape codes for file output
-        .with_span_events(FmtSpan::FULL);
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogRotation {
+    #[default]
+    Hourly,
+    Daily,
+    Size,
+    Never,
+}
 
-    // Console logging layer (with color)
-    let console_layer = fmt::layer()
-        .with_writer(std::io::stdout) // Or use stderr if you prefer
-        .with_ansi(true) // Enable ANSI escape codes for console output
-        .with_span_events(FmtSpan::FULL);
+#[derive(Args, Debug, Clone, Default)]
+pub struct LogOptions {
+    #[clap(short = 'd', long, env = "LOGS_DIR")]
+    pub logs_dir: Option<String>,
+    #[clap(long, env = "LOG_FORMAT", value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    #[clap(long, env = "LOG_ROTATION", value_enum, default_value_t = LogRotation::Hourly)]
+    pub log_rotation: LogRotation,
+    /// Maximum size of a log file in MB, used with the size based rotation
+    #[clap(long, env = "LOG_MAX_SIZE", default_value_t = 100)]
+    pub log_max_size: u64,
+    /// Number of rotated log files to keep, all are kept if not set (size rotation keeps 10)
+    #[clap(long, env = "LOG_MAX_FILES")]
+    pub log_max_files: Option<usize>,
+    /// Console log level or filter directives, defaults to RUST_LOG or INFO
+    #[clap(long, env = "CONSOLE_LOG_LEVEL")]
+    pub console_log_level: Option<String>,
+    /// File log level or filter directives, defaults to RUST_LOG or INFO
+    #[clap(long, env = "FILE_LOG_LEVEL")]
+    pub file_log_level: Option<String>,
+    #[clap(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Sets up console logging and, if a logs dir is configured, file logging.
+/// The returned guard has to be kept alive to flush the file logs.
+pub fn setup_tracing(options: &LogOptions, file: &str) -> Option<WorkerGuard> {
+    match &options.logs_dir {
+        Some(dir) => Some(setup_tracing_file_console(dir, file, options)),
+        None => {
+            setup_tracing_console(options);
+            None
+        }
+    }
+}
+
+pub fn setup_tracing_file_console(dir: &str, file: &str, options: &LogOptions) -> WorkerGuard {
+    // File logging layer (without color)
+    let (file_non_blocking, guard) = match options.log_rotation {
+        LogRotation::Size => tracing_appender::non_blocking(
+            SizeRollingAppender::new(
+                dir,
+                file,
+                options.log_max_size * BYTES_PER_MB,
+                options.log_max_files.unwrap_or(10),
+            )
+            .unwrap_or_else(|err| panic!("Failed to create the log file in {}: {}", dir, err)),
+        ),
+        rotation => tracing_appender::non_blocking(time_rolling_appender(
+            dir,
+            file,
+            rotation,
+            options.log_max_files,
+        )),
+    };
+
+    let file_layer = fmt_layer(file_non_blocking, options.log_format, false)
+        .with_filter(env_filter(options.file_log_level.as_deref()));
 
-    // Combining the layers and setting the global maximum level
+    // Combining the layers, each with its own level
     tracing_subscriber::registry()
         .with(file_layer)
-        .with(console_layer)
-        .with(env_filter) // Set max level for both layers
+        .with(console_layer(options))
+        .with(otlp_endpoint_layer(options))
         .init();
 
     guard
 }
 
-pub fn setup_tracing_console() {
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("INFO"));
-
-    // Console logging layer
-    let console_layer = fmt::layer()
-        .with_writer(std::io::stdout) // Output to console (stdout)
-        .with_ansi(false) // Disable ANSI escape codes
-        .with_span_events(FmtSpan::FULL); // Optional: Include span events
-
+pub fn setup_tracing_console(options: &LogOptions) {
     // Initialize the subscriber with the console layer
     tracing_subscriber::registry()
-        .with(console_layer)
-        .with(env_filter) // Set log level
+        .with(console_layer(options))
+        .with(otlp_endpoint_layer(options))
         .init();
 }
+
+fn console_layer<S>(options: &LogOptions) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    // Colors only for human readable output on a terminal
+    let ansi = options.log_format == LogFormat::Text && std::io::stdout().is_terminal();
+    fmt_layer(std::io::stdout, options.log_format, ansi)
+        .with_filter(env_filter(options.console_log_level.as_deref()))
+}
+
+fn otlp_endpoint_layer<S>(options: &LogOptions) -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    options
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| otlp_layer(endpoint).with_filter(env_filter(None)))
+}
+
+fn fmt_layer<S, W>(writer: W, format: LogFormat, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> fmt::MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_span_events(FmtSpan::FULL);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+fn env_filter(level: Option<&str>) -> EnvFilter {
+    match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LEVEL)),
+    }
+}
+
+fn time_rolling_appender(
+    dir: &str,
+    file: &str,
+    rotation: LogRotation,
+    max_files: Option<usize>,
+) -> RollingFileAppender {
+    let rotation = match rotation {
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+        _ => Rotation::HOURLY,
+    };
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file);
+    if let Some(max_files) = max_files {
+        builder = builder.max_log_files(max_files);
+    }
+    builder
+        .build(dir)
+        .unwrap_or_else(|err| panic!("Failed to create the log file in {}: {}", dir, err))
+}
+
+/// Flushes pending spans to the OTLP collector, if an exporter was set up.
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+fn otlp_layer<S>(endpoint: &str) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let endpoint = otlp_traces_endpoint(endpoint);
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&endpoint)
+        .build()
+        .unwrap_or_else(|err| panic!("Failed to build the OTLP exporter {}: {}", endpoint, err));
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::TokioCurrentThread)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            SERVICE_NAME,
+        )]))
+        .build();
+    let tracer = provider.tracer(SERVICE_NAME);
+    opentelemetry::global::set_tracer_provider(provider);
+
+    tracing_opentelemetry::layer().with_tracer(tracer)
+}
+
+/// The collector base URL (e.g. `http://localhost:4318`) is accepted as well as the full traces URL.
+fn otlp_traces_endpoint(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with(OTLP_TRACES_PATH) {
+        endpoint.to_string()
+    } else {
+        format!("{}{}", endpoint, OTLP_TRACES_PATH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_otlp_traces_endpoint() {
+        assert_eq!(
+            otlp_traces_endpoint("http://localhost:4318"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            otlp_traces_endpoint("http://localhost:4318/"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            otlp_traces_endpoint("http://localhost:4318/v1/traces"),
+            "http://localhost:4318/v1/traces"
+        );
+    }
+}